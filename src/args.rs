@@ -0,0 +1,491 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "pngme", about = "Hide secret messages in PNG files")]
+pub struct Cli {
+    /// Only print errors and the data a command was asked for
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print extra detail such as chunk offsets and crcs
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+/// How much status output commands print alongside their results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Hide a message in a new chunk
+    Encode(EncodeArgs),
+    /// Hide a message in every PNG file in a directory
+    EncodeDir(EncodeDirArgs),
+    /// Print the message stored in a chunk
+    Decode(DecodeArgs),
+    /// Remove the first chunk of a type, or all of them
+    Remove(RemoveArgs),
+    /// Change the type of a chunk without touching its data
+    Swap(SwapArgs),
+    /// Replace the data of the first chunk of a type, keeping its position
+    ReplaceData(ReplaceDataArgs),
+    /// Change a chunk's property bits by flipping the case of its type letters
+    SetFlags(SetFlagsArgs),
+    /// Copy a chunk from one PNG file into another
+    Copy(CopyArgs),
+    /// Write a copy of one PNG file with the private ancillary chunks of another added
+    Merge(MergeArgs),
+    /// Show which chunks were added, removed or modified between two PNG files
+    Diff(DiffArgs),
+    /// Check that re-encoding every chunk reproduces the file byte for byte
+    Roundtrip(RoundtripArgs),
+    /// List the chunks of a PNG file
+    Print(PrintArgs),
+    /// Summarize the size and chunk makeup of a PNG file
+    Stats(StatsArgs),
+    /// Show how many chunks a message of a given size would be split into
+    Plan(PlanArgs),
+    /// Explain what the letters of a chunk type say about it
+    Info(InfoArgs),
+    /// Check the crc of every chunk
+    Verify(VerifyArgs),
+    /// Search chunk data for a string
+    Find(FindArgs),
+    /// Recompute the crc of every chunk
+    Repair(RepairArgs),
+    /// Write a canonical copy of a PNG file
+    Normalize(NormalizeArgs),
+    /// Sort the ancillary chunks of a PNG file into a deterministic order
+    Canonicalize(CanonicalizeArgs),
+    /// Remove every ancillary chunk to shrink the file
+    Minify(MinifyArgs),
+    /// Write the data of every chunk to its own file, named by index and type
+    Export(ExportArgs),
+    /// Rebuild a PNG file from chunk files written by export
+    Assemble(AssembleArgs),
+}
+
+/// Encode arguments. When the chunk type or message is missing and stdin is a terminal, encode
+/// asks for them.
+#[derive(Debug, Default, Clone, Args)]
+pub struct EncodeArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: Option<String>,
+    pub message: Option<String>,
+    /// Pick an ancillary private chunk type at random and print it
+    #[arg(long, conflicts_with = "format")]
+    pub random_type: bool,
+    /// Give the chunk type as 8 hex digits, such as 52755374 for RuSt; with --force the bytes
+    /// needn't be letters
+    #[arg(long, value_name = "HEX", conflicts_with_all = ["random_type", "format"])]
+    pub type_hex: Option<String>,
+    /// Seed for --random-type, so the same type is picked every run
+    #[arg(long, requires = "random_type")]
+    pub seed: Option<u64>,
+    /// Read the message from this file instead of the command line
+    #[arg(long)]
+    pub message_file: Option<PathBuf>,
+    /// Write the result here instead of overwriting the input file
+    #[arg(short, long = "output")]
+    pub output_file: Option<PathBuf>,
+    /// Treat the message as base64 and store the decoded bytes
+    #[arg(long)]
+    pub base64: bool,
+    /// Replace the data of an existing chunk of this type instead of adding another
+    #[arg(long)]
+    pub overwrite: bool,
+    /// How to store the message; the text formats always use their own chunk type
+    #[arg(long, value_enum, default_value_t)]
+    pub format: MessageFormat,
+    /// Keyword for the text formats, such as Comment or Author
+    #[arg(long)]
+    pub keyword: Option<String>,
+    /// Language tag for --format itxt, such as en-GB
+    #[arg(long)]
+    pub language: Option<String>,
+    /// The keyword translated into --language, for --format itxt
+    #[arg(long)]
+    pub translated_keyword: Option<String>,
+    /// Prefix the data with a header recording the content type
+    #[arg(long)]
+    pub envelope: bool,
+    /// Compress the message with zlib before storing it, using iTXt's own compression flag with
+    /// --format itxt
+    #[arg(long)]
+    pub compress: bool,
+    /// Encrypt the message. Without a value the passphrase comes from PNGME_PASSPHRASE or a
+    /// prompt, keeping it out of shell history
+    #[arg(long, value_name = "PASSPHRASE")]
+    pub encrypt: Option<Option<String>>,
+    /// Store the message as base64 text, applied after compression and encryption
+    #[arg(long)]
+    pub armor: bool,
+    /// Don't warn when the chunk type is critical and private
+    #[arg(long)]
+    pub force: bool,
+    /// Refuse critical private chunk types instead of warning
+    #[arg(long, conflicts_with = "force")]
+    pub strict: bool,
+    /// Add the chunk even if one with the same type and data already exists
+    #[arg(long)]
+    pub allow_duplicate: bool,
+    /// Warn when the message is larger than this many bytes (default 1 MiB)
+    #[arg(long, value_name = "BYTES")]
+    pub max_size: Option<u64>,
+    /// Split the message across chunks of at most this many bytes
+    #[arg(long, value_name = "SIZE", conflicts_with = "overwrite")]
+    pub split: Option<usize>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+/// How encode stores a message and decode reads it back.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// The message bytes as they are, in any chunk type
+    #[default]
+    Raw,
+    /// A tEXt chunk holding latin-1 text under a keyword
+    Text,
+    /// A zTXt chunk holding compressed latin-1 text under a keyword
+    Ztxt,
+    /// An iTXt chunk holding UTF-8 text under a keyword
+    Itxt,
+}
+
+impl MessageFormat {
+    /// The chunk type a text format must be stored in, or `None` for raw.
+    pub fn chunk_type(self) -> Option<&'static str> {
+        match self {
+            MessageFormat::Raw => None,
+            MessageFormat::Text => Some("tEXt"),
+            MessageFormat::Ztxt => Some("zTXt"),
+            MessageFormat::Itxt => Some("iTXt"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct EncodeDirArgs {
+    pub dir: PathBuf,
+    pub chunk_type: String,
+    pub message: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+/// Options shared by every command that writes a PNG back to disk.
+#[derive(Debug, Default, Clone, Args)]
+pub struct WriteArgs {
+    /// Write the file even if it is missing IHDR or IEND
+    #[arg(long)]
+    pub no_validate: bool,
+    /// Report what would change without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Copy the existing file to <name>.bak before overwriting it, or pick a mode with
+    /// --backup=MODE
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "simple"
+    )]
+    pub backup: Option<BackupMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackupMode {
+    /// Always write <name>.bak, replacing any older backup
+    Simple,
+    /// Write <name>.bak.1, <name>.bak.2, ... keeping older backups
+    Numbered,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct DecodeArgs {
+    pub file_path: PathBuf,
+    /// Defaults to the format's own chunk type for the text formats
+    #[arg(required_unless_present_any = ["auto", "format"])]
+    pub chunk_type: Option<String>,
+    /// Read the chunk as this format, printing only the text of the text formats
+    #[arg(long, value_enum)]
+    pub format: Option<MessageFormat>,
+    /// Print the chunk data as base64 instead of text
+    #[arg(long)]
+    pub base64: bool,
+    /// Write the raw chunk data to this file instead of printing it
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Join the data of every chunk of this type in file order
+    #[arg(long)]
+    pub join: bool,
+    /// Also print the chunk's property flags and crc to stderr
+    #[arg(long)]
+    pub meta: bool,
+    /// Passphrase for a message stored with encode --encrypt. Without a value it comes from
+    /// PNGME_PASSPHRASE or a prompt; PNGME_PASSPHRASE is also used when the flag is left out
+    #[arg(long)]
+    pub passphrase: Option<Option<String>>,
+    /// Print every ancillary chunk that holds UTF-8 text, for when the type is unknown
+    #[arg(long, conflicts_with_all = ["chunk_type", "base64", "out", "join", "meta", "format"])]
+    pub auto: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    /// Write the removed chunk's data to stdout instead of a confirmation
+    #[arg(long)]
+    pub print: bool,
+    /// Remove every chunk of the type, succeeding even if there are none
+    #[arg(long)]
+    pub all: bool,
+    /// Remove the Nth (zero-based) chunk of the type instead of the first
+    #[arg(long, value_name = "N", conflicts_with = "all")]
+    pub nth: Option<usize>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct DiffArgs {
+    pub old_file: PathBuf,
+    pub new_file: PathBuf,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CopyArgs {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct MergeArgs {
+    pub base: PathBuf,
+    pub extra: PathBuf,
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct SwapArgs {
+    pub file_path: PathBuf,
+    pub old_type: String,
+    pub new_type: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ReplaceDataArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    pub message: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct SetFlagsArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    /// Make the chunk critical (uppercase first letter) or ancillary
+    #[arg(long, value_name = "BOOL")]
+    pub critical: Option<bool>,
+    /// Make the chunk public (uppercase second letter) or private
+    #[arg(long, value_name = "BOOL")]
+    pub public: Option<bool>,
+    /// Set the reserved bit to its valid value (uppercase third letter) or not
+    #[arg(long, value_name = "BOOL")]
+    pub reserved_valid: Option<bool>,
+    /// Make the chunk safe to copy (lowercase fourth letter) or unsafe
+    #[arg(long, value_name = "BOOL")]
+    pub safe_to_copy: Option<bool>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RoundtripArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct PrintArgs {
+    pub file_path: PathBuf,
+    /// Also report structural problems such as duplicate critical chunks
+    #[arg(long)]
+    pub lint: bool,
+    /// Print the chunk list as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Print the contents of every chunk holding valid UTF-8 instead of the chunk list
+    #[arg(long, conflicts_with = "json")]
+    pub messages: bool,
+    /// Never color the chunk list, even on a terminal
+    #[arg(long)]
+    pub no_color: bool,
+    /// Only list chunks whose type matches this glob, where ? is one letter and * any run
+    #[arg(long, value_name = "PATTERN")]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct StatsArgs {
+    pub file_path: PathBuf,
+    /// Print the statistics as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Print sizes as exact byte counts instead of KiB, MiB and so on
+    #[arg(long)]
+    pub bytes: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PlanArgs {
+    pub message_len: usize,
+    pub max_chunk_size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    pub chunk_type: String,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    /// Percentage of chunks allowed to have an invalid crc before failing
+    #[arg(long, default_value_t = 0.0)]
+    pub threshold: f64,
+}
+
+#[derive(Debug, Args)]
+pub struct NormalizeArgs {
+    pub file_path: PathBuf,
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CanonicalizeArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct MinifyArgs {
+    pub file_path: PathBuf,
+    /// Keep ancillary chunks of this type (may be repeated)
+    #[arg(long, value_name = "TYPE")]
+    pub keep: Vec<String>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ExportArgs {
+    pub file_path: PathBuf,
+    pub out_dir: PathBuf,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct AssembleArgs {
+    pub in_dir: PathBuf,
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct FindArgs {
+    pub file_path: PathBuf,
+    pub pattern: String,
+    /// Match ASCII letters regardless of case
+    #[arg(long)]
+    pub ignore_case: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_verbosity() {
+        let verbosity = |args: &[&str]| Cli::try_parse_from(args).unwrap().verbosity();
+        assert_eq!(verbosity(&["pngme", "print", "a.png"]), Verbosity::Normal);
+        assert_eq!(
+            verbosity(&["pngme", "-q", "print", "a.png"]),
+            Verbosity::Quiet
+        );
+        assert_eq!(
+            verbosity(&["pngme", "print", "a.png", "--verbose"]),
+            Verbosity::Verbose
+        );
+        assert!(Cli::try_parse_from(["pngme", "-q", "-v", "print", "a.png"]).is_err());
+    }
+
+    #[test]
+    fn test_backup_before_positionals() {
+        let remove = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Command::Remove(args) => args,
+            command => panic!("parsed {:?}", command),
+        };
+        let args = remove(&["pngme", "remove", "--backup", "file.png", "ruSt"]);
+        assert_eq!(args.write.backup, Some(BackupMode::Simple));
+        assert_eq!(args.file_path, PathBuf::from("file.png"));
+        assert_eq!(args.chunk_type, "ruSt");
+
+        let args = remove(&["pngme", "remove", "--backup=numbered", "file.png", "ruSt"]);
+        assert_eq!(args.write.backup, Some(BackupMode::Numbered));
+        assert_eq!(
+            remove(&["pngme", "remove", "file.png", "ruSt"])
+                .write
+                .backup,
+            None
+        );
+    }
+}
@@ -1,18 +1,178 @@
 use crate::ChunkType;
-use crc::{Crc, CRC_32_ISO_HDLC};
+use crc::{Crc, CRC_32_BZIP2, CRC_32_ISCSI, CRC_32_ISO_HDLC};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
 
+const CHUNK_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const ISCSI_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+const BZIP2_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_BZIP2);
+
+/// Which crc algorithm covers a chunk's type and data.
+///
+/// PNG requires [`CrcKind::IsoHdlc`]; the others are for experiments and for reading files from
+/// tools that got it wrong.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CrcKind {
+    #[default]
+    IsoHdlc,
+    /// CRC-32C, the Castagnoli polynomial
+    Iscsi,
+    /// ISO-HDLC's polynomial without bit reflection
+    Bzip2,
+}
+
+impl CrcKind {
+    fn algorithm(self) -> &'static Crc<u32> {
+        match self {
+            CrcKind::IsoHdlc => &CHUNK_CRC,
+            CrcKind::Iscsi => &ISCSI_CRC,
+            CrcKind::Bzip2 => &BZIP2_CRC,
+        }
+    }
+}
+
+/// Computes the crc over the chunk type and data without joining them into one buffer.
+fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    checksum_with(&CHUNK_CRC, chunk_type, data)
+}
+
+fn checksum_with(crc: &Crc<u32>, chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut digest = crc.digest();
+    digest.update(chunk_type.as_bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
+/// Encodes a string as latin-1, failing on characters outside that range.
+fn latin1_bytes(s: &str) -> crate::Result<Vec<u8>> {
+    s.chars()
+        .map(|c| u8::try_from(c).map_err(|_| format!("{:?} is not a latin-1 character", c).into()))
+        .collect()
+}
+
+/// Checks a text chunk keyword and encodes it: 1-79 printable latin-1 characters, with no
+/// leading, trailing or consecutive spaces.
+fn keyword_bytes(chunk_type: &str, keyword: &str) -> crate::Result<Vec<u8>> {
+    let bytes = latin1_bytes(keyword)?;
+    if bytes.is_empty() || bytes.len() > 79 {
+        return Err(format!("{} keyword must be 1-79 characters", chunk_type).into());
+    }
+    if let Some(byte) = bytes.iter().find(|x| !matches!(x, 32..=126 | 161..=255)) {
+        return Err(format!(
+            "{} keyword must be printable, but has the byte {:#04x}",
+            chunk_type, byte
+        )
+        .into());
+    }
+    if bytes.starts_with(b" ") || bytes.ends_with(b" ") || bytes.windows(2).any(|x| x == b"  ") {
+        return Err(format!(
+            "{} keyword must not have leading, trailing or consecutive spaces",
+            chunk_type
+        )
+        .into());
+    }
+    Ok(bytes)
+}
+
+/// Fills as much of `buf` as the reader allows, returning how many bytes were read.
+pub(crate) fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// The fields of an `iTXt` international text chunk.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ITxt {
+    pub keyword: String,
+    /// Whether the text is stored zlib-compressed
+    pub compressed: bool,
+    /// An RFC 3066 language tag such as `en-GB`, or empty if unknown
+    pub language: String,
+    /// The keyword translated into `language`
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+/// The only compression method the PNG specification defines for `zTXt` and `iTXt`.
+const ZTXT_DEFLATE: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Chunk {
-    length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
     crc: u32,
+    /// The length field as parsed, kept so truncated chunks can be told apart
+    declared_length: u32,
+}
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkError {
+    /// Not enough bytes for the length, type and crc fields
+    TooShort,
+    /// The length field claims more data than the buffer holds, as in a truncated file
+    LengthMismatch {
+        declared: u32,
+        available: usize,
+    },
+    /// The length field is too large to index memory with on this platform
+    LengthOverflow(u32),
+    /// The type bytes are not ASCII letters
+    BadChunkType([u8; 4]),
+    InvalidCrc,
+    InvalidUtf8,
+    /// The data is longer than the spec's limit of 2^31-1 bytes
+    DataTooLong(usize),
+    /// The type's third letter is lowercase, which the spec reserves
+    ReservedBit([u8; 4]),
+    /// The type is critical, which the builder was told to refuse
+    CriticalType([u8; 4]),
 }
-#[derive(Debug)]
-pub struct ChunkError;
 impl std::fmt::Display for ChunkError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Invalid Chunk")
+        match self {
+            ChunkError::TooShort => write!(f, "Chunk is too short"),
+            ChunkError::LengthMismatch {
+                declared,
+                available,
+            } => write!(
+                f,
+                "Chunk declares {} data bytes but only {} are available",
+                declared, available
+            ),
+            ChunkError::LengthOverflow(length) => write!(
+                f,
+                "Chunk declares {} data bytes, more than this platform can address",
+                length
+            ),
+            ChunkError::BadChunkType(bytes) => write!(f, "Invalid chunk type {:?}", bytes),
+            ChunkError::InvalidCrc => write!(f, "Chunk CRC does not match its contents"),
+            ChunkError::InvalidUtf8 => write!(f, "Chunk data is not valid UTF-8"),
+            ChunkError::DataTooLong(length) => write!(
+                f,
+                "Chunk data is {} bytes, more than the limit of {}",
+                length,
+                Chunk::MAX_LENGTH
+            ),
+            ChunkError::ReservedBit(bytes) => write!(
+                f,
+                "Chunk type {} has a lowercase third letter, which is reserved",
+                String::from_utf8_lossy(bytes)
+            ),
+            ChunkError::CriticalType(bytes) => write!(
+                f,
+                "Chunk type {} is critical; decoders that don't know it will reject the file",
+                String::from_utf8_lossy(bytes)
+            ),
+        }
     }
 }
 
@@ -21,58 +181,107 @@ impl std::error::Error for ChunkError {}
 impl TryFrom<&[u8]> for Chunk {
     type Error = ChunkError;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        // Ensure that the slice has at least 4 bytes
-        if value.len() < 4 {
-            return Err(ChunkError);
-        }
-
-        // Take the first 4 bytes and convert them to u32
-        let length = u32::from_be_bytes(value[0..4].try_into().unwrap());
-        let chunk_type = ChunkType::try_from([value[4], value[5], value[6], value[7]]).unwrap();
-        let data: Vec<u8> = value[8..8 + length as usize].to_vec();
-        let crc = u32::from_be_bytes(value[8 + length as usize..].try_into().unwrap());
-
-        let crc_expected =
-            Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&value[4..8 + length as usize]);
-
-        if crc == crc_expected {
-            Ok(Chunk {
-                length,
-                chunk_type,
-                data,
-                crc,
-            })
-        } else {
-            Err(ChunkError)
-        }
+        Chunk::try_from_with_crc(value, CrcKind::default())
     }
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let str = std::str::from_utf8(&self.data()).unwrap_or("Invalid UTF-8");
+        let str = std::str::from_utf8(self.data()).unwrap_or("Invalid UTF-8");
 
-        write!(f, "{}", str)
+        write!(
+            f,
+            "{} ({} bytes, crc {}): {}",
+            self.chunk_type,
+            self.length(),
+            self.crc_hex(),
+            str
+        )
     }
 }
 
 impl Chunk {
+    /// The most data bytes the spec allows in one chunk.
+    pub const MAX_LENGTH: u32 = i32::MAX as u32;
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let length = data.len() as u32;
-        let chunk_crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let mut buf: Vec<u8> = Vec::new();
-        buf.extend_from_slice(&chunk_type.bytes());
-        buf.extend_from_slice(&data);
-        let crc = chunk_crc.checksum(&buf);
+        Chunk::new_with_crc(chunk_type, data, CrcKind::default())
+    }
+    /// Builds a chunk from raw type bytes, failing if they aren't a valid chunk type.
+    pub fn build(type_bytes: [u8; 4], data: Vec<u8>) -> Result<Chunk, ChunkError> {
+        let chunk_type =
+            ChunkType::try_from(type_bytes).map_err(|_| ChunkError::BadChunkType(type_bytes))?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+    /// Builds a chunk whose crc uses `kind`. Only [`CrcKind::IsoHdlc`] gives valid PNG.
+    pub fn new_with_crc(chunk_type: ChunkType, data: Vec<u8>, kind: CrcKind) -> Chunk {
+        Chunk::new_batch(chunk_type, data, kind.algorithm())
+    }
+    /// Builds a chunk using a caller-owned crc table, for tools creating many chunks at once.
+    ///
+    /// `crc` must use the `CRC_32_ISO_HDLC` algorithm for the chunk to be valid PNG.
+    pub fn new_batch(chunk_type: ChunkType, data: Vec<u8>, crc: &Crc<u32>) -> Chunk {
+        let crc = checksum_with(crc, &chunk_type, &data);
         Chunk {
-            length,
             chunk_type,
+            declared_length: data.len() as u32,
             data,
             crc,
         }
     }
+    /// Parses a chunk, checking its stored crc against one computed with `kind`.
+    pub fn try_from_with_crc(value: &[u8], kind: CrcKind) -> Result<Chunk, ChunkError> {
+        Ok(ChunkRef::parse_with_crc(value, kind)?.to_chunk())
+    }
+    /// Parses a chunk without comparing its crc, keeping the stored crc as read.
+    ///
+    /// A chunk that claims more data than the buffer holds is kept with the bytes that are there
+    /// and the last four as its crc; [`Chunk::is_length_consistent`] reports it.
+    pub fn try_from_unchecked(value: &[u8]) -> Result<Chunk, ChunkError> {
+        Ok(ChunkRef::parse_unchecked(value)?.to_chunk())
+    }
+    /// Reads one chunk from a stream, checking its crc.
+    ///
+    /// Returns `None` at a clean end of stream. The data buffer grows as bytes arrive, so a
+    /// bogus declared length can't force a huge allocation up front.
+    pub fn from_reader<R: Read>(reader: &mut R) -> crate::Result<Option<Chunk>> {
+        let mut bytes = vec![0; 8];
+        match read_full(reader, &mut bytes)? {
+            0 => return Ok(None),
+            8 => {}
+            _ => return Err(ChunkError::TooShort.into()),
+        }
+        let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        reader.take(length as u64 + 4).read_to_end(&mut bytes)?;
+        Ok(Some(Chunk::try_from(bytes.as_slice())?))
+    }
+    /// Checks the stored crc against one computed from the type and data.
+    pub fn verify_crc(&self) -> bool {
+        self.verify_crc_with(CrcKind::default())
+    }
+    /// Like [`Chunk::verify_crc`], for a chunk written with another crc algorithm.
+    pub fn verify_crc_with(&self, kind: CrcKind) -> bool {
+        self.crc == checksum_with(kind.algorithm(), &self.chunk_type, &self.data)
+    }
+    /// The crc the type and data should have, whatever was stored.
+    pub fn computed_crc(&self) -> u32 {
+        checksum(&self.chunk_type, &self.data)
+    }
+    /// The data length, always derived from the data so it can't go stale.
     pub fn length(&self) -> u32 {
-        self.length
+        self.data.len() as u32
+    }
+    /// The length field as it was read, which a crafted or truncated file may get wrong.
+    pub fn declared_length(&self) -> u32 {
+        self.declared_length
+    }
+    /// Whether the declared length matches the data actually held.
+    pub fn is_length_consistent(&self) -> bool {
+        self.declared_length as usize == self.data.len()
+    }
+    /// Size of the chunk on disk: length, type, data and crc.
+    pub fn total_bytes(&self) -> usize {
+        4 + 4 + self.data.len() + 4
     }
     pub fn chunk_type(&self) -> &ChunkType {
         &self.chunk_type
@@ -80,21 +289,329 @@ impl Chunk {
     pub fn data(&self) -> &[u8] {
         &self.data[..]
     }
+    /// Replaces the data and recomputes the crc so the chunk stays valid.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.crc = checksum(&self.chunk_type, &data);
+        self.declared_length = data.len() as u32;
+        self.data = data;
+    }
     pub fn crc(&self) -> u32 {
         self.crc
     }
+    /// The crc as 8 lowercase hex digits, the way other PNG tools show it.
+    pub fn crc_hex(&self) -> String {
+        format!("{:08x}", self.crc)
+    }
     pub fn data_as_string(&self) -> Result<String, ChunkError> {
-        let string = std::str::from_utf8(&self.data());
+        let string = std::str::from_utf8(self.data());
         match string {
             Ok(string) => Ok(string.to_string()),
-            Err(_) => Err(ChunkError),
+            Err(_) => Err(ChunkError::InvalidUtf8),
         }
     }
+    /// Builds a `tEXt` chunk holding a latin-1 keyword and text separated by a null byte.
+    pub fn new_text(keyword: &str, text: &str) -> crate::Result<Chunk> {
+        let mut data = keyword_bytes("tEXt", keyword)?;
+        data.push(0);
+        data.extend(latin1_bytes(text)?);
+        Ok(Chunk::new(ChunkType::from_str("tEXt")?, data))
+    }
+    /// Builds a `zTXt` chunk: keyword, null, compression method 0 and the zlib-compressed text.
+    pub fn new_ztxt(keyword: &str, text: &str) -> crate::Result<Chunk> {
+        let mut data = keyword_bytes("zTXt", keyword)?;
+        data.extend([0, ZTXT_DEFLATE]);
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+        encoder.write_all(&latin1_bytes(text)?)?;
+        Ok(Chunk::new(ChunkType::from_str("zTXt")?, encoder.finish()?))
+    }
+    /// Builds an uncompressed `iTXt` chunk with no language tag or translated keyword.
+    pub fn new_itxt(keyword: &str, text: &str) -> crate::Result<Chunk> {
+        Chunk::from_itxt(&ITxt {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            ..Default::default()
+        })
+    }
+    /// Builds an `iTXt` chunk: latin-1 keyword, null, compression flag and method, language tag,
+    /// null, UTF-8 translated keyword, null, then the UTF-8 text, zlib-compressed if flagged.
+    pub fn from_itxt(itxt: &ITxt) -> crate::Result<Chunk> {
+        let mut data = keyword_bytes("iTXt", &itxt.keyword)?;
+        if !itxt.language.is_ascii() || itxt.language.contains('\0') {
+            return Err(format!("{:?} is not an ASCII language tag", itxt.language).into());
+        }
+        if itxt.translated_keyword.contains('\0') {
+            return Err("iTXt translated keyword must not contain null bytes".into());
+        }
+        data.extend([0, itxt.compressed as u8, ZTXT_DEFLATE]);
+        data.extend_from_slice(itxt.language.as_bytes());
+        data.push(0);
+        data.extend_from_slice(itxt.translated_keyword.as_bytes());
+        data.push(0);
+        if itxt.compressed {
+            let mut encoder = ZlibEncoder::new(data, Compression::default());
+            encoder.write_all(itxt.text.as_bytes())?;
+            data = encoder.finish()?;
+        } else {
+            data.extend_from_slice(itxt.text.as_bytes());
+        }
+        Ok(Chunk::new(ChunkType::from_str("iTXt")?, data))
+    }
+    /// Splits a `tEXt` chunk into its keyword and text.
+    ///
+    /// Returns `None` for other chunk types or when the null separator is missing.
+    pub fn as_text(&self) -> Option<(String, String)> {
+        if self.chunk_type.as_bytes() != b"tEXt" {
+            return None;
+        }
+        let separator = self.data.iter().position(|&x| x == 0)?;
+        let keyword = self.data[..separator].iter().map(|&x| x as char).collect();
+        let text = self.data[separator + 1..]
+            .iter()
+            .map(|&x| x as char)
+            .collect();
+        Some((keyword, text))
+    }
+    /// Splits a `zTXt` chunk into its keyword and inflated text.
+    pub fn as_ztxt(&self) -> crate::Result<(String, String)> {
+        if self.chunk_type.as_bytes() != b"zTXt" {
+            return Err(format!("{} is not a zTXt chunk", self.chunk_type).into());
+        }
+        let separator = self
+            .data
+            .iter()
+            .position(|&x| x == 0)
+            .ok_or("zTXt chunk has no keyword separator")?;
+        let keyword = self.data[..separator].iter().map(|&x| x as char).collect();
+        match self.data.get(separator + 1) {
+            Some(&ZTXT_DEFLATE) => {}
+            Some(method) => {
+                return Err(format!("Unsupported zTXt compression method {}", method).into())
+            }
+            None => return Err("zTXt chunk has no compression method".into()),
+        }
+        let mut text = Vec::new();
+        ZlibDecoder::new(&self.data[separator + 2..]).read_to_end(&mut text)?;
+        Ok((keyword, text.iter().map(|&x| x as char).collect()))
+    }
+    /// Parses an `iTXt` chunk, inflating its text if the compression flag is set.
+    pub fn as_itxt(&self) -> crate::Result<ITxt> {
+        if self.chunk_type.as_bytes() != b"iTXt" {
+            return Err(format!("{} is not an iTXt chunk", self.chunk_type).into());
+        }
+        let separator = self
+            .data
+            .iter()
+            .position(|&x| x == 0)
+            .ok_or("iTXt chunk has no keyword separator")?;
+        let keyword = self.data[..separator].iter().map(|&x| x as char).collect();
+        let compressed = match self.data.get(separator + 1..separator + 3) {
+            Some([0, _]) => false,
+            Some([1, ZTXT_DEFLATE]) => true,
+            Some([1, method]) => {
+                return Err(format!("Unsupported iTXt compression method {}", method).into())
+            }
+            Some([flag, _]) => return Err(format!("Invalid iTXt compression flag {}", flag).into()),
+            _ => return Err("iTXt chunk is missing its compression fields".into()),
+        };
+        let mut fields = self.data[separator + 3..].splitn(3, |&x| x == 0);
+        let (Some(language), Some(translated_keyword), Some(text)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err("iTXt chunk is missing its language fields".into());
+        };
+        let text = if compressed {
+            let mut inflated = Vec::new();
+            ZlibDecoder::new(text).read_to_end(&mut inflated)?;
+            inflated
+        } else {
+            text.to_vec()
+        };
+        let utf8 = |bytes: Vec<u8>, field: &str| {
+            String::from_utf8(bytes).map_err(|_| format!("iTXt {} is not valid UTF-8", field))
+        };
+        Ok(ITxt {
+            keyword,
+            compressed,
+            language: utf8(language.to_vec(), "language tag")?,
+            translated_keyword: utf8(translated_keyword.to_vec(), "translated keyword")?,
+            text: utf8(text, "text")?,
+        })
+    }
+    /// Appends the serialized chunk to `bytes` without building an intermediate buffer.
+    pub fn write_into(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.header_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes.extend_from_slice(&self.crc_bytes());
+    }
+    /// The length and type fields as they appear on disk.
+    pub fn header_bytes(&self) -> [u8; 8] {
+        let mut header = [0; 8];
+        header[..4].copy_from_slice(&self.length().to_be_bytes());
+        header[4..].copy_from_slice(self.chunk_type.as_bytes());
+        header
+    }
+    /// The crc field as it appears on disk.
+    pub fn crc_bytes(&self) -> [u8; 4] {
+        self.crc.to_be_bytes()
+    }
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.data().to_vec()
+        let mut bytes = Vec::with_capacity(self.total_bytes());
+        self.write_into(&mut bytes);
+        bytes
     }
 }
 
+/// A chunk whose data borrows from the buffer it was parsed from, for scanning files without
+/// copying every chunk's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+    declared_length: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses the chunk at the start of `value`, checking its length and crc like
+    /// [`Chunk::try_from`].
+    pub fn parse(value: &'a [u8]) -> Result<ChunkRef<'a>, ChunkError> {
+        ChunkRef::parse_with_crc(value, CrcKind::default())
+    }
+
+    /// Like [`ChunkRef::parse`], comparing the stored crc against one computed with `kind`.
+    pub fn parse_with_crc(value: &'a [u8], kind: CrcKind) -> Result<ChunkRef<'a>, ChunkError> {
+        let chunk = ChunkRef::parse_unchecked(value)?;
+        if chunk.declared_length as usize != chunk.data.len() {
+            Err(ChunkError::LengthMismatch {
+                declared: chunk.declared_length,
+                available: chunk.data.len(),
+            })
+        } else if chunk.verify_crc_with(kind) {
+            Ok(chunk)
+        } else {
+            Err(ChunkError::InvalidCrc)
+        }
+    }
+
+    /// Like [`Chunk::try_from_unchecked`], without copying the data.
+    pub fn parse_unchecked(value: &'a [u8]) -> Result<ChunkRef<'a>, ChunkError> {
+        // Ensure that the slice has at least the length, type and crc fields
+        if value.len() < 12 {
+            return Err(ChunkError::TooShort);
+        }
+
+        // Take the first 4 bytes and convert them to u32
+        let length = u32::from_be_bytes(value[0..4].try_into().unwrap());
+        // on 32-bit targets a length near u32::MAX would wrap to a small index
+        let data_end = usize::try_from(length)
+            .ok()
+            .and_then(|x| x.checked_add(8))
+            .ok_or(ChunkError::LengthOverflow(length))?
+            .min(value.len() - 4);
+
+        let type_bytes = [value[4], value[5], value[6], value[7]];
+        let chunk_type =
+            ChunkType::try_from(type_bytes).map_err(|_| ChunkError::BadChunkType(type_bytes))?;
+        let crc = u32::from_be_bytes(value[data_end..data_end + 4].try_into().unwrap());
+
+        Ok(ChunkRef {
+            chunk_type,
+            data: &value[8..data_end],
+            crc,
+            declared_length: length,
+        })
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        4 + 4 + self.data.len() + 4
+    }
+
+    pub fn verify_crc(&self) -> bool {
+        self.verify_crc_with(CrcKind::default())
+    }
+
+    pub fn verify_crc_with(&self, kind: CrcKind) -> bool {
+        self.crc == checksum_with(kind.algorithm(), &self.chunk_type, self.data)
+    }
+
+    /// Copies the data into an owned [`Chunk`], keeping the stored crc as read.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            chunk_type: self.chunk_type.clone(),
+            data: self.data.to_vec(),
+            crc: self.crc,
+            declared_length: self.declared_length,
+        }
+    }
+}
+
+/// Assembles a chunk, checking it against the spec on [`ChunkBuilder::build`].
+#[derive(Debug)]
+pub struct ChunkBuilder {
+    type_bytes: [u8; 4],
+    data: Vec<u8>,
+    refuse_critical: bool,
+}
+
+impl ChunkBuilder {
+    pub fn new(type_bytes: [u8; 4]) -> ChunkBuilder {
+        ChunkBuilder {
+            type_bytes,
+            data: Vec::new(),
+            refuse_critical: false,
+        }
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> ChunkBuilder {
+        self.data = data;
+        self
+    }
+
+    /// Makes [`ChunkBuilder::build`] fail for critical types, which a decoder that doesn't
+    /// know them must refuse to display.
+    pub fn refuse_critical(mut self) -> ChunkBuilder {
+        self.refuse_critical = true;
+        self
+    }
+
+    /// Checks the type and data length, then computes the crc.
+    pub fn build(self) -> Result<Chunk, ChunkError> {
+        let chunk_type = ChunkType::try_from(self.type_bytes)
+            .map_err(|_| ChunkError::BadChunkType(self.type_bytes))?;
+        if !chunk_type.is_reserved_bit_valid() {
+            return Err(ChunkError::ReservedBit(self.type_bytes));
+        }
+        if self.refuse_critical && chunk_type.is_critical() {
+            return Err(ChunkError::CriticalType(self.type_bytes));
+        }
+        check_data_len(self.data.len())?;
+        Ok(Chunk::new(chunk_type, self.data))
+    }
+}
+
+fn check_data_len(length: usize) -> Result<(), ChunkError> {
+    if length > Chunk::MAX_LENGTH as usize {
+        return Err(ChunkError::DataTooLong(length));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,12 +647,250 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_incremental_checksum() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!".as_bytes();
+        assert_eq!(checksum(&chunk_type, data), 2882656334);
+
+        let joined: Vec<u8> = chunk_type.bytes().iter().chain(data).copied().collect();
+        assert_eq!(CHUNK_CRC.checksum(&joined), 2882656334);
+    }
+
+    #[test]
+    fn test_itxt() {
+        let chunk = Chunk::new_itxt("Title", "Grüße, 世界").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+        assert_eq!(&chunk.data()[..11], b"Title\0\0\0\0\0G");
+        let itxt = chunk.as_itxt().unwrap();
+        assert_eq!(itxt.keyword, "Title");
+        assert!(!itxt.compressed);
+        assert_eq!(itxt.text, "Grüße, 世界");
+        assert!(Chunk::new_itxt("", "text").is_err());
+        assert!(Chunk::new_text("Title", "text").unwrap().as_itxt().is_err());
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        for compressed in [false, true] {
+            let itxt = ITxt {
+                keyword: String::from("Title"),
+                compressed,
+                language: String::from("ja"),
+                translated_keyword: String::from("タイトル"),
+                text: "こんにちは、世界! ".repeat(10),
+            };
+            let chunk = Chunk::from_itxt(&itxt).unwrap();
+            assert_eq!(chunk.data()[6], compressed as u8);
+            assert_eq!(chunk.as_itxt().unwrap(), itxt);
+        }
+        let bad_language = ITxt {
+            keyword: String::from("Title"),
+            language: String::from("日本"),
+            ..Default::default()
+        };
+        assert!(Chunk::from_itxt(&bad_language).is_err());
+    }
+
+    #[test]
+    fn test_header_and_crc_bytes() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.header_bytes(), *b"\0\0\0\x2aRuSt");
+        assert_eq!(chunk.crc_bytes(), 2882656334u32.to_be_bytes());
+
+        let mut joined = chunk.header_bytes().to_vec();
+        joined.extend_from_slice(chunk.data());
+        joined.extend_from_slice(&chunk.crc_bytes());
+        assert_eq!(joined, chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_ref() {
+        let bytes = testing_chunk().as_bytes();
+        let chunk_ref = ChunkRef::parse(&bytes).unwrap();
+        assert_eq!(chunk_ref.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk_ref.data(), &bytes[8..bytes.len() - 4]);
+        assert_eq!(chunk_ref.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(chunk_ref.total_bytes(), bytes.len());
+        assert_eq!(chunk_ref.to_chunk(), testing_chunk());
+
+        let mut corrupt = bytes.clone();
+        corrupt[9] ^= 0xff;
+        assert_eq!(ChunkRef::parse(&corrupt), Err(ChunkError::InvalidCrc));
+        let chunk_ref = ChunkRef::parse_unchecked(&corrupt).unwrap();
+        assert!(!chunk_ref.verify_crc());
+        assert_eq!(chunk_ref.crc(), testing_chunk().crc());
+
+        assert_eq!(
+            ChunkRef::parse(&bytes[..bytes.len() - 1]),
+            Err(ChunkError::LengthMismatch {
+                declared: chunk_ref.length(),
+                available: chunk_ref.data().len() - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chunk_builder() {
+        let chunk = ChunkBuilder::new(*b"ruSt")
+            .data(b"hey".to_vec())
+            .refuse_critical()
+            .build()
+            .unwrap();
+        assert_eq!(chunk, Chunk::build(*b"ruSt", b"hey".to_vec()).unwrap());
+        assert!(ChunkBuilder::new(*b"RuSt").build().is_ok());
+
+        let err = |builder: ChunkBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            err(ChunkBuilder::new(*b"ru1t")),
+            ChunkError::BadChunkType(*b"ru1t")
+        );
+        assert_eq!(
+            err(ChunkBuilder::new(*b"rust")),
+            ChunkError::ReservedBit(*b"rust")
+        );
+        assert_eq!(
+            err(ChunkBuilder::new(*b"RuSt").refuse_critical()),
+            ChunkError::CriticalType(*b"RuSt")
+        );
+        assert!(err(ChunkBuilder::new(*b"rust"))
+            .to_string()
+            .contains("rust"));
+    }
+
+    #[test]
+    fn test_check_data_len() {
+        assert!(check_data_len(Chunk::MAX_LENGTH as usize).is_ok());
+        assert_eq!(
+            check_data_len(Chunk::MAX_LENGTH as usize + 1),
+            Err(ChunkError::DataTooLong(Chunk::MAX_LENGTH as usize + 1))
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let chunk = Chunk::build(*b"RuSt", b"hey".to_vec()).unwrap();
+        assert_eq!(
+            chunk,
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"hey".to_vec())
+        );
+        assert!(chunk.verify_crc());
+
+        assert_eq!(
+            Chunk::build(*b"Ru1t", Vec::new()),
+            Err(ChunkError::BadChunkType(*b"Ru1t"))
+        );
+        assert!(Chunk::build([82, 117, 0, 116], Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_new_with_crc() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!".to_vec();
+        let standard = Chunk::new(chunk_type.clone(), data.clone());
+        assert_eq!(
+            Chunk::new_with_crc(chunk_type.clone(), data.clone(), CrcKind::IsoHdlc),
+            standard
+        );
+
+        let chunk = Chunk::new_with_crc(chunk_type, data, CrcKind::Iscsi);
+        assert_ne!(chunk.crc(), standard.crc());
+        assert!(chunk.verify_crc_with(CrcKind::Iscsi));
+        assert!(!chunk.verify_crc());
+
+        let bytes = chunk.as_bytes();
+        assert_eq!(
+            Chunk::try_from_with_crc(&bytes, CrcKind::Iscsi).unwrap(),
+            chunk
+        );
+        assert_eq!(Chunk::try_from(&bytes[..]), Err(ChunkError::InvalidCrc));
+        assert!(ChunkRef::parse_with_crc(&bytes, CrcKind::Iscsi).is_ok());
+        assert_eq!(ChunkRef::parse(&bytes), Err(ChunkError::InvalidCrc));
+    }
+
+    #[test]
+    fn test_new_batch_matches_new() {
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        for message in ["", "a", "This is where your secret message will be!"] {
+            let batch = Chunk::new_batch(
+                ChunkType::from_str("RuSt").unwrap(),
+                message.as_bytes().to_vec(),
+                &crc,
+            );
+            let single = Chunk::new(
+                ChunkType::from_str("RuSt").unwrap(),
+                message.as_bytes().to_vec(),
+            );
+            assert_eq!(batch.crc(), single.crc());
+            assert_eq!(batch.as_bytes(), single.as_bytes());
+        }
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
         assert_eq!(chunk.length(), 42);
     }
 
+    #[test]
+    fn test_chunk_length_follows_data() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), Vec::new());
+        assert_eq!(chunk.length(), 0);
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![1; 300]);
+        assert_eq!(chunk.length(), 300);
+        assert_eq!(&chunk.as_bytes()[0..4], &300u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_set_data() {
+        let mut chunk = testing_chunk();
+        let old_crc = chunk.crc();
+        chunk.set_data(b"A different message".to_vec());
+
+        assert!(chunk.verify_crc());
+        assert_ne!(chunk.crc(), old_crc);
+        assert_eq!(chunk.length(), 19);
+        assert_eq!(chunk.data(), b"A different message");
+    }
+
+    #[test]
+    fn test_crc_hex() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.crc_hex(), "abd1d84e");
+
+        let chunk = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        assert_eq!(chunk.crc_hex(), "ae426082");
+        assert_eq!(chunk.to_string(), "IEND (0 bytes, crc ae426082): ");
+    }
+
+    #[test]
+    fn test_chunk_from_reader() {
+        let chunk = testing_chunk();
+        let mut bytes = chunk.as_bytes();
+        bytes.extend(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()).as_bytes());
+        let mut reader = std::io::Cursor::new(bytes);
+
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), Some(chunk));
+        let iend = Chunk::from_reader(&mut reader).unwrap().unwrap();
+        assert_eq!(iend.chunk_type().to_string(), "IEND");
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_chunk_from_reader_truncated() {
+        let bytes = testing_chunk().as_bytes();
+        assert!(Chunk::from_reader(&mut &bytes[..5]).is_err());
+        assert!(Chunk::from_reader(&mut &bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_total_bytes() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.total_bytes(), 54);
+        assert_eq!(chunk.total_bytes(), chunk.as_bytes().len());
+    }
+
     #[test]
     fn test_chunk_type() {
         let chunk = testing_chunk();
@@ -201,7 +956,209 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert_eq!(chunk.err(), Some(ChunkError::InvalidCrc));
+    }
+
+    #[test]
+    fn test_chunk_length_mismatch() {
+        let data_length: u32 = 100;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = [7u8; 20];
+        let crc: u32 = 0;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert_eq!(
+            chunk.err(),
+            Some(ChunkError::LengthMismatch {
+                declared: 100,
+                available: 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_near_max_length() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[..4].copy_from_slice(&(u32::MAX - 4).to_be_bytes());
+        let err = Chunk::try_from(bytes.as_ref()).unwrap_err();
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(err, ChunkError::LengthOverflow(u32::MAX - 4));
+        #[cfg(not(target_pointer_width = "32"))]
+        assert_eq!(
+            err,
+            ChunkError::LengthMismatch {
+                declared: u32::MAX - 4,
+                available: 42
+            }
+        );
+    }
+
+    #[test]
+    fn test_unchecked_keeps_inconsistent_length() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[3] = 100;
+        let chunk = Chunk::try_from_unchecked(&bytes).unwrap();
+        assert_eq!(chunk.declared_length(), 100);
+        assert_eq!(chunk.data().len(), 42);
+        assert!(!chunk.is_length_consistent());
+        assert!(testing_chunk().is_length_consistent());
+    }
+
+    #[test]
+    fn test_chunk_bad_type() {
+        let chunk_data: Vec<u8> = [0, 0, 0, 1]
+            .iter()
+            .chain([82, 49, 255, 116].iter())
+            .chain([0, 0, 0, 0, 0].iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert_eq!(
+            chunk.err(),
+            Some(ChunkError::BadChunkType([82, 49, 255, 116]))
+        );
+    }
+
+    #[test]
+    fn test_chunk_too_short() {
+        let chunk = Chunk::try_from([0, 0, 0, 0, 82, 117].as_ref());
+        assert_eq!(chunk.err(), Some(ChunkError::TooShort));
+    }
+
+    #[test]
+    fn test_unchecked_chunk_keeps_bad_crc() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from_unchecked(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.crc(), 2882656333);
+        assert!(!chunk.verify_crc());
+        assert!(testing_chunk().verify_crc());
+    }
+
+    #[test]
+    fn test_chunk_clone_eq() {
+        let chunk = testing_chunk();
+        let clone = chunk.clone();
+        assert_eq!(chunk, clone);
+
+        let other = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"other".to_vec());
+        assert_ne!(chunk, other);
+    }
+
+    #[test]
+    fn test_text_chunk() {
+        let chunk = Chunk::new_text("Comment", "caf\u{e9} au lait").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9 au lait");
+        assert_eq!(
+            chunk.as_text(),
+            Some((String::from("Comment"), String::from("caf\u{e9} au lait")))
+        );
+    }
+
+    #[test]
+    fn test_text_chunk_missing_separator() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"no separator".to_vec(),
+        );
+        assert_eq!(chunk.as_text(), None);
+    }
+
+    #[test]
+    fn test_as_text_other_type() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"key\0value".to_vec());
+        assert_eq!(chunk.as_text(), None);
+    }
+
+    #[test]
+    fn test_text_chunk_invalid() {
+        assert!(Chunk::new_text("", "text").is_err());
+        assert!(Chunk::new_text("Comment", "not latin-1 \u{1F600}").is_err());
+    }
+
+    #[test]
+    fn test_keyword_rules() {
+        // the limit counts latin-1 characters, not UTF-8 bytes
+        let long = "\u{f6}".repeat(79);
+        assert_eq!(keyword_bytes("tEXt", &long).unwrap(), vec![0xf6; 79]);
+        assert!(keyword_bytes("tEXt", &format!("{}o", long)).is_err());
+        assert_eq!(keyword_bytes("tEXt", "K\u{f6}ln").unwrap(), b"K\xf6ln");
+        assert!(keyword_bytes("tEXt", "Creation Time").is_ok());
+
+        for bad in [
+            "Tab\there",
+            "Null\0",
+            "No\u{a0}break",
+            " Lead",
+            "Trail ",
+            "Two  spaces",
+        ] {
+            assert!(keyword_bytes("tEXt", bad).is_err(), "{:?}", bad);
+        }
+        let err = keyword_bytes("zTXt", "Tab\there").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "zTXt keyword must be printable, but has the byte 0x09"
+        );
+    }
+
+    #[test]
+    fn test_ztxt_round_trip() {
+        let text = "caf\u{e9} ".repeat(50);
+        let chunk = Chunk::new_ztxt("Description", &text).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().starts_with(b"Description\0\0"));
+        assert!(chunk.data().len() < text.len());
+        assert_eq!(
+            chunk.as_ztxt().unwrap(),
+            (String::from("Description"), text)
+        );
+    }
+
+    #[test]
+    fn test_ztxt_unsupported_method() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("zTXt").unwrap(),
+            b"key\0\x05data".to_vec(),
+        );
+        let err = chunk.as_ztxt().err().unwrap();
+        assert_eq!(err.to_string(), "Unsupported zTXt compression method 5");
+    }
+
+    #[test]
+    fn test_ztxt_invalid() {
+        assert!(Chunk::new_ztxt("", "text").is_err());
+        assert!(testing_chunk().as_ztxt().is_err());
+        let chunk = Chunk::new(
+            ChunkType::from_str("zTXt").unwrap(),
+            b"key\0\0junk".to_vec(),
+        );
+        assert!(chunk.as_ztxt().is_err());
     }
 
     #[test]
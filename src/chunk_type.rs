@@ -14,7 +14,7 @@ impl std::fmt::Display for ChunkTypeError {
 
 impl std::error::Error for ChunkTypeError {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChunkType {
     bytes: [u8; 4],
     is_valid: bool,
@@ -23,29 +23,55 @@ pub struct ChunkType {
     is_reserved_bit_valid: bool,
     is_safe_to_copy: bool,
 }
-#[allow(dead_code)]
 impl ChunkType {
     fn new(bytes: [u8; 4]) -> Result<Self, ChunkTypeError> {
-        let is_valid = bytes.iter().all(|&x| x.is_ascii_alphabetic());
-        let is_critical = bytes[0].is_ascii_uppercase();
-        let is_public = bytes[1].is_ascii_uppercase();
-        let is_reserved_bit_valid = bytes[2].is_ascii_uppercase();
-        let is_safe_to_copy = bytes[3].is_ascii_lowercase();
-
-        if is_valid {
-            Ok(ChunkType {
-                bytes,
-                is_valid,
-                is_critical,
-                is_public,
-                is_reserved_bit_valid,
-                is_safe_to_copy,
-            })
+        let chunk_type = ChunkType::from_bytes_unchecked(bytes);
+        if chunk_type.is_valid {
+            Ok(chunk_type)
         } else {
             Err(ChunkTypeError)
         }
     }
 
+    /// Builds a chunk type without rejecting non-alphabetic bytes.
+    ///
+    /// The property flags are still computed, but callers must check `is_valid()` before
+    /// treating the result as a real PNG chunk type.
+    pub fn from_bytes_unchecked(bytes: [u8; 4]) -> ChunkType {
+        ChunkType {
+            bytes,
+            is_valid: bytes.iter().all(|&x| x.is_ascii_alphabetic()),
+            is_critical: bytes[0].is_ascii_uppercase(),
+            is_public: bytes[1].is_ascii_uppercase(),
+            is_reserved_bit_valid: bytes[2].is_ascii_uppercase(),
+            is_safe_to_copy: bytes[3].is_ascii_lowercase(),
+        }
+    }
+
+    /// Parses exactly four bytes without rejecting non-alphabetic ones, for tools that want to
+    /// report why a type is invalid. Use `FromStr` for strict parsing.
+    pub fn parse_lenient(s: &str) -> Option<ChunkType> {
+        let bytes: [u8; 4] = s.as_bytes().try_into().ok()?;
+        Some(ChunkType::from_bytes_unchecked(bytes))
+    }
+
+    /// Picks an ancillary, private, safe-to-copy type with the reserved bit valid (a pattern
+    /// like `abCd`), the same for the same seed.
+    pub fn random_private(seed: u64) -> ChunkType {
+        // splitmix64, plenty for picking letters
+        let mut state = seed;
+        let mut next_letter = || {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            b'a' + ((z ^ (z >> 31)) % 26) as u8
+        };
+        let mut bytes = [next_letter(), next_letter(), next_letter(), next_letter()];
+        bytes[2] = bytes[2].to_ascii_uppercase();
+        ChunkType::from_bytes_unchecked(bytes)
+    }
+
     pub fn is_valid(&self) -> bool {
         self.is_valid
     }
@@ -54,6 +80,11 @@ impl ChunkType {
         self.is_critical
     }
 
+    /// Ancillary chunks may be ignored by decoders that don't recognize them.
+    pub fn is_ancillary(&self) -> bool {
+        !self.is_critical
+    }
+
     pub fn is_reserved_bit_valid(&self) -> bool {
         self.is_reserved_bit_valid
     }
@@ -62,6 +93,11 @@ impl ChunkType {
         self.is_public
     }
 
+    /// Private chunks are not part of the PNG specification or its registered extensions.
+    pub fn is_private(&self) -> bool {
+        !self.is_public
+    }
+
     pub fn is_safe_to_copy(&self) -> bool {
         self.is_safe_to_copy
     }
@@ -69,6 +105,11 @@ impl ChunkType {
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
+
+    /// Borrows the type bytes, avoiding the copy made by [`ChunkType::bytes`].
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.bytes
+    }
 }
 
 impl Display for ChunkType {
@@ -82,7 +123,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
     type Error = ChunkTypeError;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        let bytes = value.clone();
+        let bytes = value;
 
         ChunkType::new(bytes)
     }
@@ -108,6 +149,20 @@ mod tests {
     use std::convert::TryFrom;
     use std::str::FromStr;
 
+    #[test]
+    pub fn test_random_private() {
+        for seed in 0..100 {
+            let chunk_type = ChunkType::random_private(seed);
+            assert!(chunk_type.is_valid());
+            assert!(chunk_type.is_ancillary());
+            assert!(chunk_type.is_private());
+            assert!(chunk_type.is_reserved_bit_valid());
+            assert!(chunk_type.is_safe_to_copy());
+        }
+        assert_eq!(ChunkType::random_private(7), ChunkType::random_private(7));
+        assert_ne!(ChunkType::random_private(7), ChunkType::random_private(8));
+    }
+
     #[test]
     pub fn test_chunk_type_from_bytes() {
         let expected = [82, 117, 83, 116];
@@ -147,6 +202,34 @@ mod tests {
         assert!(!chunk.is_public());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_ancillary() {
+        for (chunk_type, expected) in [
+            ("IHDR", false),
+            ("IEND", false),
+            ("tEXt", true),
+            ("ruSt", true),
+        ] {
+            let chunk = ChunkType::from_str(chunk_type).unwrap();
+            assert_eq!(chunk.is_ancillary(), expected, "{}", chunk_type);
+            assert_eq!(chunk.is_ancillary(), !chunk.is_critical());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_private() {
+        for (chunk_type, expected) in [
+            ("IHDR", false),
+            ("tEXt", false),
+            ("ruSt", true),
+            ("RuSt", true),
+        ] {
+            let chunk = ChunkType::from_str(chunk_type).unwrap();
+            assert_eq!(chunk.is_private(), expected, "{}", chunk_type);
+            assert_eq!(chunk.is_private(), !chunk.is_public());
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_is_reserved_bit_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -196,4 +279,34 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_from_bytes_unchecked() {
+        let chunk = ChunkType::from_bytes_unchecked(*b"Ru1t");
+        assert!(!chunk.is_valid());
+        assert_eq!(chunk.bytes(), *b"Ru1t");
+        assert!(chunk.is_critical());
+
+        let chunk = ChunkType::from_bytes_unchecked(*b"RuSt");
+        assert!(chunk.is_valid());
+        assert_eq!(chunk, ChunkType::from_str("RuSt").unwrap());
+    }
+
+    #[test]
+    pub fn test_parse_lenient() {
+        let chunk = ChunkType::parse_lenient("Ru1t").unwrap();
+        assert!(!chunk.is_valid());
+        assert_eq!(&chunk.bytes(), b"Ru1t");
+        assert!(ChunkType::parse_lenient("RuSt").unwrap().is_valid());
+        assert!(ChunkType::parse_lenient("Rus").is_none());
+        assert!(ChunkType::parse_lenient("RuStx").is_none());
+        assert!(ChunkType::from_str("Ru1t").is_err());
+    }
+
+    #[test]
+    pub fn test_as_bytes() {
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(*chunk.as_bytes(), chunk.bytes());
+        assert_eq!(chunk.as_bytes(), b"RuSt");
+    }
 }
@@ -0,0 +1,44 @@
+use std::io::IsTerminal;
+
+/// ANSI foreground colors used for terminal output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Cyan,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+/// Whether stdout should be colored: it must be a terminal and `NO_COLOR` must be unset.
+pub fn stdout_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+/// Wraps `text` in the escape codes for `color` when `enabled` is set.
+pub fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(paint("IHDR", Color::Cyan, true), "\x1b[36mIHDR\x1b[0m");
+        assert_eq!(paint("IHDR", Color::Red, false), "IHDR");
+    }
+}
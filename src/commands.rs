@@ -0,0 +1,3384 @@
+use crate::args::{
+    AssembleArgs, BackupMode, CanonicalizeArgs, CopyArgs, DecodeArgs, DiffArgs, EncodeArgs,
+    EncodeDirArgs, ExportArgs, FindArgs, MergeArgs, MessageFormat, MinifyArgs, PrintArgs,
+    RemoveArgs, RepairArgs, ReplaceDataArgs, SetFlagsArgs, StatsArgs, SwapArgs, VerifyArgs,
+    WriteArgs,
+};
+use crate::chunk::{Chunk, ChunkRef, ITxt};
+use crate::color::{self, Color};
+use crate::envelope::{self, ContentType};
+use crate::png::{Png, PngError};
+use crate::transform::Pipeline;
+use crate::ChunkType;
+use crate::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+/// Stores the message in a new chunk, written to the output file or back to the input.
+pub fn encode(args: &EncodeArgs) -> Result<Report> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let mut report = Report::default();
+    let random;
+    let args = if args.random_type {
+        let seed = args.seed.unwrap_or_else(|| {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u32(process::id());
+            hasher.finish()
+        });
+        let chunk_type = ChunkType::random_private(seed).to_string();
+        // the type is the only way to find the message again, so print it even with --quiet
+        report.push(Level::Output, format!("Chunk type: {}", chunk_type));
+        random = with_chunk_type(args, chunk_type, "--random-type")?;
+        &random
+    } else {
+        args
+    };
+    let type_bytes = args.type_hex.as_deref().map(parse_type_hex).transpose()?;
+    let from_hex;
+    let args = match type_bytes {
+        Some(bytes) => {
+            let name = String::from_utf8_lossy(&bytes).into_owned();
+            from_hex = with_chunk_type(args, name, "--type-hex")?;
+            &from_hex
+        }
+        None => args,
+    };
+    let formatted;
+    let args = match args.format.chunk_type() {
+        Some(spec_type) => {
+            formatted = with_spec_type(args, spec_type)?;
+            &formatted
+        }
+        None => args,
+    };
+    let prompted;
+    let args = if args.chunk_type.is_none() || needs_message(args) {
+        if !io::stdin().is_terminal() {
+            return Err("Give a chunk type and message, or run in a terminal to be asked".into());
+        }
+        prompted = prompt_encode(args, &mut io::stdin().lock(), &mut io::stderr())?;
+        &prompted
+    } else {
+        args
+    };
+    let type_name = args.chunk_type.as_deref().ok_or("No chunk type given")?;
+    let chunk_type = match type_bytes {
+        // --force lets --type-hex write deliberately invalid types, for testing decoders
+        Some(bytes) if args.force => ChunkType::from_bytes_unchecked(bytes),
+        Some(bytes) => ChunkType::try_from(bytes)
+            .map_err(|_| format!("{:02x?} is not a valid chunk type, see --force", bytes))?,
+        None => ChunkType::from_str(type_name)?,
+    };
+    if chunk_type.is_critical() && chunk_type.is_private() {
+        let warning = format!(
+            "{} is a critical private chunk type, strict decoders may refuse the image; \
+             use an ancillary type (lowercase first letter) instead",
+            chunk_type
+        );
+        if args.strict {
+            return Err(warning.into());
+        }
+        if !args.force {
+            report.push(Level::Notice, format!("Warning: {}", warning));
+        }
+    }
+    let mut data = message_bytes(args)?;
+    let max_size = args.max_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+    if let Some(warning) = message_size_warning(data.len() as u64, max_size) {
+        report.push(Level::Notice, format!("Warning: {}", warning));
+    }
+    if args.format != MessageFormat::Itxt
+        && (args.language.is_some() || args.translated_keyword.is_some())
+    {
+        return Err("--language and --translated-keyword only apply to --format itxt".into());
+    }
+    if args.format != MessageFormat::Raw {
+        if (args.compress && args.format != MessageFormat::Itxt)
+            || args.encrypt.is_some()
+            || args.armor
+            || args.envelope
+        {
+            return Err("Transforms and --envelope only apply to --format raw".into());
+        }
+        if args.split.is_some() {
+            return Err("--split only applies to --format raw".into());
+        }
+        let keyword = args
+            .keyword
+            .as_deref()
+            .ok_or("The text formats need a --keyword")?;
+        data = text_chunk(args, keyword, data)?.data().to_vec();
+    }
+    let passphrase = match &args.encrypt {
+        Some(given) => Some(passphrase(given.as_deref(), env_var, hidden_prompt, true)?),
+        None => None,
+    };
+    // --format itxt has already used --compress for the chunk's own compression flag
+    let compress = args.compress && args.format == MessageFormat::Raw;
+    let pipeline = Pipeline::new(compress, passphrase.as_deref(), args.armor);
+    if !pipeline.is_empty() {
+        data = envelope::wrap(ContentType::Transformed, &pipeline.encode(data)?);
+    } else if args.envelope {
+        let content_type = if std::str::from_utf8(&data).is_ok() {
+            ContentType::Text
+        } else {
+            ContentType::Binary
+        };
+        data = envelope::wrap(content_type, &data);
+    }
+
+    let summary = if args.overwrite && png.chunk_by_type(type_name).is_some() {
+        check_chunk_len(data.len() as u64)?;
+        let summary = format!("Replaced chunk {} ({} bytes)", chunk_type, data.len());
+        png.replace_first_chunk(type_name, Chunk::new(chunk_type, data))?;
+        summary
+    } else if let Some(size) = args.split {
+        let pieces = split_message(&data, size)?;
+        for piece in &pieces {
+            check_chunk_len(piece.len() as u64)?;
+        }
+        let summary = format!(
+            "Added {} chunk(s) of type {} ({} bytes)",
+            pieces.len(),
+            chunk_type,
+            data.len()
+        );
+        for piece in pieces {
+            png.append_chunk(Chunk::new(chunk_type.clone(), piece));
+        }
+        summary
+    } else {
+        check_chunk_len(data.len() as u64)?;
+        let chunk = Chunk::new(chunk_type, data);
+        if !args.allow_duplicate && png.chunks().contains(&chunk) {
+            let note = format!(
+                "Message already present in a {} chunk, not adding it again",
+                chunk.chunk_type()
+            );
+            if args.output_file.is_none() {
+                // nothing changed, so don't rewrite the file or back it up
+                report.push(Level::Info, note);
+                return Ok(report);
+            }
+            note
+        } else {
+            let summary = format!(
+                "Added chunk {} ({} bytes)",
+                chunk.chunk_type(),
+                chunk.length()
+            );
+            png.append_chunk(chunk);
+            summary
+        }
+    };
+    report.extend(save(
+        &png,
+        args.output_file.as_ref().unwrap_or(&args.file_path),
+        &args.write,
+        &summary,
+    )?);
+    report.extend(log_chunks(&png, type_name));
+    Ok(report)
+}
+
+/// Sets a text format's chunk type. A lone positional after the file is taken as the message,
+/// since the type is implied.
+fn with_spec_type(args: &EncodeArgs, spec_type: &str) -> Result<EncodeArgs> {
+    let mut args = args.clone();
+    match args.chunk_type.take() {
+        Some(message) if needs_message(&args) => args.message = Some(message),
+        Some(chunk_type) if chunk_type != spec_type => {
+            return Err(format!(
+                "--format {} stores a {} chunk, not {}",
+                format!("{:?}", args.format).to_lowercase(),
+                spec_type,
+                chunk_type
+            )
+            .into())
+        }
+        _ => {}
+    }
+    args.chunk_type = Some(spec_type.to_string());
+    Ok(args)
+}
+
+/// Fills in a chunk type chosen by `flag`, taking a lone positional after the file as the
+/// message.
+fn with_chunk_type(args: &EncodeArgs, chunk_type: String, flag: &str) -> Result<EncodeArgs> {
+    let mut args = args.clone();
+    match args.chunk_type.take() {
+        Some(message) if needs_message(&args) => args.message = Some(message),
+        Some(_) => {
+            return Err(format!("{} gives the chunk type; give only the message", flag).into())
+        }
+        None => {}
+    }
+    args.chunk_type = Some(chunk_type);
+    Ok(args)
+}
+
+/// Parses `--type-hex`: exactly 8 hex digits, two per type byte.
+fn parse_type_hex(hex: &str) -> Result<[u8; 4]> {
+    if hex.len() != 8 || !hex.bytes().all(|x| x.is_ascii_hexdigit()) {
+        return Err(format!("{:?} is not 8 hex digits, as in 52755374", hex).into());
+    }
+    let mut bytes = [0; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(bytes)
+}
+
+/// Builds the chunk a text format stores `message` in.
+fn text_chunk(args: &EncodeArgs, keyword: &str, message: Vec<u8>) -> Result<Chunk> {
+    let text = String::from_utf8(message).map_err(|_| "Text message must be valid UTF-8")?;
+    match args.format {
+        MessageFormat::Raw => Err("Raw messages aren't stored as text".into()),
+        MessageFormat::Text => Chunk::new_text(keyword, &text),
+        MessageFormat::Ztxt => Chunk::new_ztxt(keyword, &text),
+        MessageFormat::Itxt => Chunk::from_itxt(&ITxt {
+            keyword: keyword.to_string(),
+            compressed: args.compress,
+            language: args.language.clone().unwrap_or_default(),
+            translated_keyword: args.translated_keyword.clone().unwrap_or_default(),
+            text,
+        }),
+    }
+}
+
+fn needs_message(args: &EncodeArgs) -> bool {
+    args.message.is_none() && args.message_file.is_none()
+}
+
+/// Asks on `output` for the chunk type and message when `args` lacks them, asking again until
+/// the type is valid. Returns a copy of `args` with the answers filled in.
+fn prompt_encode<R: BufRead, W: Write>(
+    args: &EncodeArgs,
+    input: &mut R,
+    output: &mut W,
+) -> Result<EncodeArgs> {
+    let mut prompted = args.clone();
+    while prompted.chunk_type.is_none() {
+        let answer = ask(input, output, "Chunk type")?;
+        match ChunkType::from_str(&answer) {
+            Ok(_) if answer.len() == 4 => prompted.chunk_type = Some(answer),
+            _ => writeln!(
+                output,
+                "{:?} is not a valid chunk type, use four letters",
+                answer
+            )?,
+        }
+    }
+    if needs_message(&prompted) {
+        prompted.message = Some(ask(input, output, "Message")?);
+    }
+    Ok(prompted)
+}
+
+fn ask<R: BufRead, W: Write>(input: &mut R, output: &mut W, question: &str) -> Result<String> {
+    write!(output, "{}: ", question)?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(format!("No {} given", question.to_lowercase()).into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Environment variable read for `--encrypt` and `--passphrase` when no value is given.
+const PASSPHRASE_VAR: &str = "PNGME_PASSPHRASE";
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Picks the passphrase for `--encrypt` or `--passphrase`: the value given on the command line,
+/// then [`PASSPHRASE_VAR`] from `env`, then `prompt`, asked twice when `confirm` is set.
+fn passphrase(
+    given: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+    mut prompt: impl FnMut(&str) -> Result<String>,
+    confirm: bool,
+) -> Result<String> {
+    if let Some(passphrase) = given {
+        return Ok(passphrase.to_string());
+    }
+    if let Some(passphrase) = env(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let passphrase = prompt("Passphrase")?;
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into());
+    }
+    if confirm && prompt("Confirm passphrase")? != passphrase {
+        return Err("Passphrases don't match".into());
+    }
+    Ok(passphrase)
+}
+
+/// Asks on the terminal with echo turned off by `stty`, refusing rather than showing the answer
+/// when echo can't be turned off.
+fn hidden_prompt(question: &str) -> Result<String> {
+    let unavailable = || {
+        format!(
+            "Can't ask for a passphrase here; set {} or give it after the flag",
+            PASSPHRASE_VAR
+        )
+    };
+    if !io::stdin().is_terminal() {
+        return Err(unavailable().into());
+    }
+    let stty = |arg: &str| {
+        process::Command::new("stty")
+            .arg(arg)
+            .stdin(process::Stdio::inherit())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    if !stty("-echo") {
+        return Err(unavailable().into());
+    }
+    let answer = ask(&mut io::stdin().lock(), &mut io::stderr(), question);
+    stty("echo");
+    eprintln!();
+    answer
+}
+
+/// Message size above which `encode` warns unless `--max-size` says otherwise.
+const DEFAULT_MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// A warning when the message is over `max_size`.
+fn message_size_warning(message_len: u64, max_size: u64) -> Option<String> {
+    (message_len > max_size).then(|| {
+        format!(
+            "message is {}, over the {} limit set by --max-size",
+            human_size(message_len as usize),
+            human_size(max_size as usize)
+        )
+    })
+}
+
+/// Rejects chunk data over [`Chunk::MAX_LENGTH`], which [`Png::from_file`] would refuse to read
+/// back. Checked on the final data, after text headers, transforms and the envelope have grown
+/// it. Takes a length rather than data so huge sizes can be tested.
+fn check_chunk_len(chunk_len: u64) -> Result<()> {
+    if chunk_len > Chunk::MAX_LENGTH as u64 {
+        return Err(format!(
+            "Chunk data is {} bytes, but a chunk can hold at most {} bytes; use --split",
+            chunk_len,
+            Chunk::MAX_LENGTH
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The file offset and crc of every chunk of `chunk_type`, as `--verbose` detail.
+fn log_chunks(png: &Png, chunk_type: &str) -> Report {
+    let mut report = Report::default();
+    let mut offset = Png::STANDARD_HEADER.len();
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() == chunk_type {
+            report.push(
+                Level::Detail,
+                format!(
+                    "{} at offset {} (crc {})",
+                    chunk.chunk_type(),
+                    offset,
+                    chunk.crc_hex()
+                ),
+            );
+        }
+        offset += chunk.total_bytes();
+    }
+    report
+}
+
+/// Cuts a message into pieces of at most `size` bytes, matching the count reported by [`plan`].
+fn split_message(data: &[u8], size: usize) -> Result<Vec<Vec<u8>>> {
+    if size == 0 {
+        return Err("Split size must be greater than zero".into());
+    }
+    if data.is_empty() {
+        return Ok(vec![Vec::new()]);
+    }
+    Ok(data.chunks(size).map(|x| x.to_vec()).collect())
+}
+
+/// Encodes the message into every `.png` file directly inside `args.dir`, returning how many
+/// files failed.
+///
+/// A failure on one file doesn't stop the others; they are all reported at the end.
+pub fn encode_dir(args: &EncodeDirArgs) -> Result<Outcome<usize>> {
+    let files = png_files(&args.dir)?;
+    let mut report = Report::default();
+    let mut failures = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let encode_args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(args.chunk_type.clone()),
+            message: Some(args.message.clone()),
+            write: args.write.clone(),
+            ..Default::default()
+        };
+        let status = match encode(&encode_args) {
+            Ok(file_report) => {
+                report.extend(file_report);
+                "ok"
+            }
+            Err(e) => {
+                failures.push((path, e));
+                "failed"
+            }
+        };
+        report.push(
+            Level::Info,
+            format!("[{}/{}] {}: {}", i + 1, files.len(), path.display(), status),
+        );
+    }
+
+    report.push(
+        Level::Info,
+        format!(
+            "Encoded {} of {} files",
+            files.len() - failures.len(),
+            files.len()
+        ),
+    );
+    for (path, e) in &failures {
+        report.push(Level::Error, format!("{}: {}", path.display(), e));
+    }
+    Ok(Outcome {
+        value: failures.len(),
+        report,
+    })
+}
+
+fn png_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if is_png && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the message from the command line or `--message-file`, decoding base64 if asked.
+fn message_bytes(args: &EncodeArgs) -> Result<Vec<u8>> {
+    let message = match (&args.message, &args.message_file) {
+        (Some(message), None) => message.as_bytes().to_vec(),
+        (None, Some(path)) => fs::read(path)?,
+        (Some(_), Some(_)) => {
+            return Err("Give either a message or --message-file, not both".into())
+        }
+        (None, None) => return Err("No message given".into()),
+    };
+
+    if args.base64 {
+        let text = String::from_utf8_lossy(&message);
+        Ok(BASE64
+            .decode(text.trim())
+            .map_err(|e| format!("Invalid base64 message: {}", e))?)
+    } else {
+        Ok(message)
+    }
+}
+
+/// Returns the data of the first chunk of the given type, or of all of them with `--join`, as
+/// text or base64. Returns `None` when the data was written to `--out` instead.
+pub fn decode(args: &DecodeArgs) -> Result<Outcome<Option<String>>> {
+    let png = Png::from_file(&args.file_path)?;
+    if let Some(spec_type) = args.format.and_then(|x| x.chunk_type()) {
+        return decode_text(&png, args, spec_type);
+    }
+    let chunk_type = match &args.chunk_type {
+        Some(chunk_type) if !args.auto => chunk_type,
+        _ => return decode_auto(&png).map(|x| Outcome::from(Some(x))),
+    };
+    let mut report = log_chunks(&png, chunk_type);
+    let data = match png.chunk_by_type(chunk_type) {
+        Some(_) if args.join => png.chunk_data_concat(chunk_type),
+        Some(chunk) => chunk.data().to_vec(),
+        None => return Err(format!("No chunk of type {} found", chunk_type).into()),
+    };
+    if args.meta {
+        if let Some(chunk) = png.chunk_by_type(chunk_type) {
+            for line in chunk_meta(chunk) {
+                report.push(Level::Notice, line);
+            }
+        }
+    }
+    let data = match envelope::unwrap(&data)? {
+        Some((content_type, payload)) => {
+            report.push(Level::Notice, format!("Content type: {}", content_type));
+            if content_type == ContentType::Transformed {
+                let passphrase = match &args.passphrase {
+                    Some(given) => {
+                        Some(passphrase(given.as_deref(), env_var, hidden_prompt, false)?)
+                    }
+                    None => env_var(PASSPHRASE_VAR),
+                };
+                Pipeline::decode(payload, passphrase.as_deref())?
+            } else {
+                payload.to_vec()
+            }
+        }
+        None => data,
+    };
+
+    let value = if let Some(out) = &args.out {
+        fs::write(out, &data)?;
+        None
+    } else if args.base64 {
+        Some(BASE64.encode(&data))
+    } else {
+        Some(String::from_utf8(data).map_err(|_| "Chunk data is not valid UTF-8")?)
+    };
+    Ok(Outcome { value, report })
+}
+
+/// Reads the text of a tEXt, zTXt or iTXt chunk, leaving out its keyword.
+fn decode_text(png: &Png, args: &DecodeArgs, spec_type: &str) -> Result<Outcome<Option<String>>> {
+    if args.base64 || args.join {
+        return Err("--base64 and --join only apply to --format raw".into());
+    }
+    let chunk_type = args.chunk_type.as_deref().unwrap_or(spec_type);
+    let chunk = png
+        .chunk_by_type(chunk_type)
+        .ok_or_else(|| format!("No chunk of type {} found", chunk_type))?;
+    let mut report = Report::default();
+    if args.meta {
+        for line in chunk_meta(chunk) {
+            report.push(Level::Notice, line);
+        }
+    }
+    let (_, text) = match chunk.chunk_type().as_bytes() {
+        b"tEXt" => chunk
+            .as_text()
+            .ok_or("tEXt chunk has no keyword separator")?,
+        b"zTXt" => chunk.as_ztxt()?,
+        b"iTXt" => {
+            let itxt = chunk.as_itxt()?;
+            (itxt.keyword, itxt.text)
+        }
+        _ => return Err(format!("{} is not a {} chunk", chunk_type, spec_type).into()),
+    };
+    let value = if let Some(out) = &args.out {
+        fs::write(out, text)?;
+        None
+    } else {
+        Some(text)
+    };
+    Ok(Outcome { value, report })
+}
+
+/// Explains each property bit of `chunk_type` and what it means for decoders.
+pub fn info(chunk_type: &str) -> Result<Vec<String>> {
+    let parsed = ChunkType::parse_lenient(chunk_type)
+        .filter(|x| x.is_valid())
+        .ok_or_else(|| {
+            format!(
+                "{:?} is not a chunk type, use four ASCII letters",
+                chunk_type
+            )
+        })?;
+    let line = |holds: bool, yes: &str, no: &str| if holds { yes } else { no }.to_string();
+    Ok(vec![
+        parsed.to_string(),
+        line(
+            parsed.is_critical(),
+            "  critical: decoders must understand it to display the image",
+            "  ancillary: decoders that don't recognize it can ignore it",
+        ),
+        line(
+            parsed.is_public(),
+            "  public: defined by the PNG specification or a registered extension",
+            "  private: an application's own type, unknown to other decoders",
+        ),
+        line(
+            parsed.is_reserved_bit_valid(),
+            "  reserved bit valid: the third letter is uppercase, as the spec requires",
+            "  reserved bit invalid: a lowercase third letter is reserved for future use",
+        ),
+        line(
+            parsed.is_safe_to_copy(),
+            "  safe to copy: editors may keep it even after changing critical chunks",
+            "  unsafe to copy: editors must drop it if they change critical chunks",
+        ),
+    ])
+}
+
+/// Describes a chunk's property flags and crc, one `name: value` line each.
+fn chunk_meta(chunk: &Chunk) -> Vec<String> {
+    let chunk_type = chunk.chunk_type();
+    vec![
+        format!("Type: {}", chunk_type),
+        format!("Critical: {}", chunk_type.is_critical()),
+        format!("Public: {}", chunk_type.is_public()),
+        format!("Reserved bit valid: {}", chunk_type.is_reserved_bit_valid()),
+        format!("Safe to copy: {}", chunk_type.is_safe_to_copy()),
+        format!("CRC: {}", chunk.crc_hex()),
+    ]
+}
+
+/// Lists every ancillary chunk whose data is non-empty, printable UTF-8 as `type: message`.
+fn decode_auto(png: &Png) -> Result<String> {
+    let candidates = auto_candidates(png);
+    if candidates.is_empty() {
+        return Err("No ancillary chunk holds UTF-8 text; \
+             if you know the chunk type, try decode <FILE> <TYPE> --base64"
+            .into());
+    }
+    let lines: Vec<String> = candidates
+        .iter()
+        .map(|(chunk_type, message)| format!("{}: {}", chunk_type, message))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+fn auto_candidates(png: &Png) -> Vec<(String, String)> {
+    png.chunks()
+        .iter()
+        .filter(|x| !x.chunk_type().is_critical() && !x.data().is_empty())
+        .filter_map(|x| Some((x.chunk_type().to_string(), x.data_as_string().ok()?)))
+        // binary data such as sRGB's single byte can happen to be valid UTF-8
+        .filter(|(_, text)| {
+            text.chars()
+                .all(|c| !c.is_control() || c.is_ascii_whitespace())
+        })
+        .collect()
+}
+
+/// Removes the first chunk of the given type, or every one with `--all`, and writes the file
+/// back. Returns the removed chunks in file order.
+pub fn remove(args: &RemoveArgs) -> Result<Outcome<Vec<Chunk>>> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let chunks = if args.all {
+        // an invalid type would quietly match nothing
+        ChunkType::from_str(&args.chunk_type)?;
+        png.remove_all_chunks(&args.chunk_type)
+    } else if let Some(n) = args.nth {
+        vec![png.remove_nth_chunk(&args.chunk_type, n)?]
+    } else {
+        vec![png.remove_first_chunk(&args.chunk_type)?]
+    };
+    if chunks.is_empty() {
+        let mut report = Report::default();
+        report.push(
+            Level::Notice,
+            format!("No chunk of type {} to remove", args.chunk_type),
+        );
+        return Ok(Outcome {
+            value: chunks,
+            report,
+        });
+    }
+    let summary = format!(
+        "Removed {} chunk(s) of type {} ({} bytes)",
+        chunks.len(),
+        args.chunk_type,
+        chunks.iter().map(|x| x.length() as usize).sum::<usize>()
+    );
+    let mut report = save(&png, &args.file_path, &args.write, &summary)?;
+    // stdout is kept for the removed data, so the confirmation goes to stderr
+    for (level, _) in &mut report.messages {
+        if *level == Level::Info {
+            *level = Level::Notice;
+        }
+    }
+    Ok(Outcome {
+        value: chunks,
+        report,
+    })
+}
+
+/// Retypes the first chunk of `old_type` as `new_type`, keeping its data and position.
+pub fn swap(args: &SwapArgs) -> Result<Report> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let new_type = ChunkType::from_str(&args.new_type)?;
+    let data = match png.chunk_by_type(&args.old_type) {
+        Some(chunk) => chunk.data().to_vec(),
+        None => return Err(format!("No chunk of type {} found", args.old_type).into()),
+    };
+
+    let summary = format!("Changed chunk {} to {}", args.old_type, new_type);
+    // Chunk::new recomputes the crc over the new type bytes
+    png.replace_first_chunk(&args.old_type, Chunk::new(new_type, data))?;
+    save(&png, &args.file_path, &args.write, &summary)
+}
+
+/// Swaps the data of the first chunk of the given type, leaving every chunk where it was.
+pub fn replace_data(args: &ReplaceDataArgs) -> Result<Report> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let chunk = png
+        .chunk_by_type_mut(&args.chunk_type)
+        .ok_or_else(|| format!("No chunk of type {} found", args.chunk_type))?;
+    let summary = format!(
+        "Replaced the data of {} ({} -> {} bytes)",
+        chunk.chunk_type(),
+        chunk.length(),
+        args.message.len()
+    );
+    chunk.set_data(args.message.as_bytes().to_vec());
+    save(&png, &args.file_path, &args.write, &summary)
+}
+
+/// Rewrites the first chunk of the given type with the requested property bits, keeping its data.
+pub fn set_flags(args: &SetFlagsArgs) -> Result<Report> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let chunk = match png.chunk_by_type(&args.chunk_type) {
+        Some(chunk) => chunk,
+        None => return Err(format!("No chunk of type {} found", args.chunk_type).into()),
+    };
+    let new_type = flagged_type(chunk.chunk_type(), args)?;
+    let summary = format!("Changed chunk {} to {}", chunk.chunk_type(), new_type);
+    let chunk = Chunk::new(new_type, chunk.data().to_vec());
+    png.replace_first_chunk(&args.chunk_type, chunk)?;
+    save(&png, &args.file_path, &args.write, &summary)
+}
+
+/// Applies the flag options to `chunk_type`. Each property lives in the case of one letter.
+fn flagged_type(chunk_type: &ChunkType, args: &SetFlagsArgs) -> Result<ChunkType> {
+    let mut bytes = chunk_type.bytes();
+    let flags = [
+        args.critical,
+        args.public,
+        args.reserved_valid,
+        // safe-to-copy is the one property marked by a lowercase letter
+        args.safe_to_copy.map(|x| !x),
+    ];
+    for (byte, uppercase) in bytes.iter_mut().zip(flags) {
+        match uppercase {
+            Some(true) => *byte = byte.to_ascii_uppercase(),
+            Some(false) => *byte = byte.to_ascii_lowercase(),
+            None => {}
+        }
+    }
+    ChunkType::try_from(bytes).map_err(|_| format!("{:?} is not a valid chunk type", bytes).into())
+}
+
+/// Appends the first chunk of the given type in `source` to `destination`.
+pub fn copy(args: &CopyArgs) -> Result<Report> {
+    let source = Png::from_file(&args.source)?;
+    let chunk = match source.chunk_by_type(&args.chunk_type) {
+        Some(chunk) => chunk.clone(),
+        None => {
+            return Err(format!(
+                "No chunk of type {} found in {}",
+                args.chunk_type,
+                args.source.display()
+            )
+            .into())
+        }
+    };
+
+    let mut destination = Png::from_file(&args.destination)?;
+    let summary = format!(
+        "Copied chunk {} ({} bytes)",
+        chunk.chunk_type(),
+        chunk.length()
+    );
+    destination.append_chunk(chunk);
+    save(&destination, &args.destination, &args.write, &summary)
+}
+
+/// Adds the private ancillary chunks of `extra` to a copy of `base`, returning how many were
+/// added. Critical chunks of `extra` are left out so the base image stays intact, as are chunks
+/// `base` already holds.
+pub fn merge(args: &MergeArgs) -> Result<Outcome<usize>> {
+    let mut png = Png::from_file(&args.base)?;
+    let extra = Png::from_file(&args.extra)?;
+    let mut merged = 0;
+    for chunk in extra.chunks() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_ancillary() && chunk_type.is_private() && !png.chunks().contains(chunk) {
+            png.append_chunk(chunk.clone());
+            merged += 1;
+        }
+    }
+    let summary = format!("Merged {} chunk(s) from {}", merged, args.extra.display());
+    let report = save(&png, &args.output_file, &args.write, &summary)?;
+    Ok(Outcome {
+        value: merged,
+        report,
+    })
+}
+
+/// Returns one line per chunk that differs between the two files.
+pub fn diff(args: &DiffArgs) -> Result<Vec<String>> {
+    let old = Png::from_file(&args.old_file)?;
+    let new = Png::from_file(&args.new_file)?;
+    Ok(diff_chunks(old.chunks(), new.chunks()))
+}
+
+enum Edit {
+    Keep,
+    Remove(usize),
+    Add(usize),
+}
+
+/// Describes how to get from `old` to `new` as `+`, `-` and `~` (modified) lines.
+///
+/// Chunks are matched on type and data with a longest common subsequence, then a removed and an
+/// added chunk of the same type between two matches are reported as one modification.
+fn diff_chunks(old: &[Chunk], new: &[Chunk]) -> Vec<String> {
+    // lcs[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Remove(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(j));
+            j += 1;
+        }
+    }
+
+    let mut lines = Vec::new();
+    for run in edits.split(|x| matches!(x, Edit::Keep)) {
+        let mut added: Vec<usize> = run
+            .iter()
+            .filter_map(|x| match x {
+                Edit::Add(j) => Some(*j),
+                _ => None,
+            })
+            .collect();
+        for edit in run {
+            match edit {
+                Edit::Remove(i) => {
+                    let chunk = &old[*i];
+                    match added
+                        .iter()
+                        .position(|&j| new[j].chunk_type() == chunk.chunk_type())
+                    {
+                        Some(pos) => {
+                            let j = added.remove(pos);
+                            lines.push(format!(
+                                "~ {}: {} ({} -> {} bytes)",
+                                j,
+                                chunk.chunk_type(),
+                                chunk.length(),
+                                new[j].length()
+                            ));
+                        }
+                        None => lines.push(format!(
+                            "- {}: {} ({} bytes)",
+                            i,
+                            chunk.chunk_type(),
+                            chunk.length()
+                        )),
+                    }
+                }
+                Edit::Add(j) => {
+                    // skip chunks already reported as a modification
+                    if let Some(pos) = added.iter().position(|x| x == j) {
+                        added.remove(pos);
+                        lines.push(format!(
+                            "+ {}: {} ({} bytes)",
+                            j,
+                            new[*j].chunk_type(),
+                            new[*j].length()
+                        ));
+                    }
+                }
+                Edit::Keep => {}
+            }
+        }
+    }
+    lines
+}
+
+/// Writes `png` to `path`, refusing structurally broken files unless `no_validate` is set.
+///
+/// On a dry run the file is still validated, but only `summary` and the resulting file size are
+/// printed.
+fn save(png: &Png, path: &Path, options: &WriteArgs, summary: &str) -> Result<Report> {
+    let mut report = Report::default();
+    if !options.no_validate {
+        png.validate()?;
+    }
+    if options.dry_run {
+        report.push(
+            Level::Info,
+            format!(
+                "{} (dry run, {} would be {} bytes)",
+                summary,
+                path.display(),
+                png.total_size()
+            ),
+        );
+        return Ok(report);
+    }
+    if let Some(mode) = options.backup {
+        if path.exists() {
+            fs::copy(path, backup_path(path, mode))?;
+        }
+    }
+    png.write_file_unchecked(path)?;
+    report.push(Level::Info, summary);
+    Ok(report)
+}
+
+/// How a message from a command is shown; `main` prints it according to the verbosity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// On stdout, even with `--quiet`
+    Output,
+    /// What the command did, on stdout unless `--quiet`
+    Info,
+    /// Warnings and side notes, on stderr unless `--quiet`
+    Notice,
+    /// Extra detail on stderr, only with `--verbose`
+    Detail,
+    /// Failures that didn't stop the command, always on stderr
+    Error,
+}
+
+/// The messages a command produced, in order.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub messages: Vec<(Level, String)>,
+}
+
+impl Report {
+    pub fn push(&mut self, level: Level, message: impl Into<String>) {
+        self.messages.push((level, message.into()));
+    }
+
+    pub fn extend(&mut self, other: Report) {
+        self.messages.extend(other.messages);
+    }
+
+    /// The messages at `level`, in order.
+    pub fn lines(&self, level: Level) -> Vec<&str> {
+        self.messages
+            .iter()
+            .filter(|(x, _)| *x == level)
+            .map(|(_, message)| message.as_str())
+            .collect()
+    }
+}
+
+/// A command's result together with the messages it produced.
+#[derive(Debug, PartialEq)]
+pub struct Outcome<T> {
+    pub value: T,
+    pub report: Report,
+}
+
+impl<T> From<T> for Outcome<T> {
+    fn from(value: T) -> Self {
+        Outcome {
+            value,
+            report: Report::default(),
+        }
+    }
+}
+
+fn backup_path(path: &Path, mode: BackupMode) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    let simple = PathBuf::from(name);
+    match mode {
+        BackupMode::Simple => simple,
+        BackupMode::Numbered => (1..)
+            .map(|n| PathBuf::from(format!("{}.{}", simple.display(), n)))
+            .find(|candidate| !candidate.exists())
+            .unwrap(),
+    }
+}
+
+/// Length, type and crc fields written around each chunk's data.
+const CHUNK_FRAMING: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Plan {
+    pub chunks: usize,
+    pub overhead: usize,
+}
+
+/// Works out how many chunks a message of `message_len` bytes needs when no chunk may hold more
+/// than `max_chunk_size` bytes, and how many bytes of chunk framing that adds to the file.
+pub fn plan(message_len: usize, max_chunk_size: usize) -> Result<Plan> {
+    if max_chunk_size == 0 {
+        return Err("Maximum chunk size must be greater than zero".into());
+    }
+    // an empty message is still stored as one empty chunk
+    let chunks = message_len.div_ceil(max_chunk_size).max(1);
+    Ok(Plan {
+        chunks,
+        overhead: chunks * CHUNK_FRAMING,
+    })
+}
+
+/// The `print` output: one entry per line, plus lint warnings meant for stderr.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Lists every chunk in the file, optionally with lint warnings.
+pub fn print(args: &PrintArgs) -> Result<Listing> {
+    // read without crc checks so corrupt chunks can be shown instead of failing the whole file
+    let png = Png::from_file_unchecked(&args.file_path)?;
+    let shown = |chunk_type: &str| {
+        args.filter
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, chunk_type))
+    };
+    let mut listing = Listing::default();
+    if args.messages {
+        for (chunk_type, text) in png.all_strings() {
+            if shown(&chunk_type) {
+                listing.lines.push(format!("{}: {}", chunk_type, text));
+            }
+        }
+    } else if args.json {
+        let chunks: Vec<ChunkInfo> = chunk_infos(png.chunks())
+            .into_iter()
+            .filter(|x| shown(&x.chunk_type))
+            .collect();
+        listing.lines.push(chunks_json(&chunks));
+    } else {
+        if let Ok((width, height)) = png.dimensions() {
+            listing.lines.push(format!("{}x{}", width, height));
+        }
+        let colored = !args.no_color && color::stdout_enabled();
+        for (i, chunk) in png.chunks().iter().enumerate() {
+            if shown(&chunk.chunk_type().to_string()) {
+                listing.lines.push(chunk_line(i, chunk, colored));
+            }
+        }
+    }
+    if args.lint {
+        listing.warnings = png.lint();
+    }
+    Ok(listing)
+}
+
+/// Matches `text` against a glob where `?` is any one character and `*` any run of them.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // where the last * was and the text position it is currently standing in for
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // let the * swallow one more character and retry
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// One line of the `print` listing, colored by whether the chunk is critical and its crc holds.
+fn chunk_line(index: usize, chunk: &Chunk, colored: bool) -> String {
+    let (kind, color) = if chunk.chunk_type().is_ancillary() {
+        ("ancillary", Color::Green)
+    } else {
+        ("critical", Color::Cyan)
+    };
+    let (crc_note, color) = if chunk.verify_crc() {
+        ("", color)
+    } else {
+        (", invalid crc", Color::Red)
+    };
+    format!(
+        "{}: {} ({} bytes, {}, crc {}{})",
+        index,
+        color::paint(&chunk.chunk_type().to_string(), color, colored),
+        chunk.length(),
+        kind,
+        chunk.crc_hex(),
+        crc_note
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: usize,
+    pub data_size: usize,
+    pub animated: bool,
+    pub frame_count: Option<u32>,
+    pub chunk_count: usize,
+    /// How many chunks of each type the file holds, in order of first appearance
+    pub counts: Vec<(String, usize)>,
+    /// Every chunk in file order, for `--json`
+    pub chunks: Vec<ChunkInfo>,
+}
+
+impl Stats {
+    /// The text report, with sizes in raw bytes or human-readable units.
+    pub fn report(&self, raw_bytes: bool) -> String {
+        let size = |bytes: usize| {
+            if raw_bytes {
+                format!("{} bytes", bytes)
+            } else {
+                human_size(bytes)
+            }
+        };
+        let mut lines = Vec::new();
+        if let Some((width, height)) = self.dimensions {
+            lines.push(format!("Dimensions: {}x{}", width, height));
+        }
+        lines.push(format!("File size: {}", size(self.file_size)));
+        lines.push(format!("Data size: {}", size(self.data_size)));
+        if self.animated {
+            match self.frame_count {
+                Some(frames) => lines.push(format!("Animated: {} frames", frames)),
+                None => lines.push(String::from("Animated: unknown frame count")),
+            }
+        }
+        lines.push(format!("Chunks: {}", self.chunk_count));
+        for (chunk_type, count) in &self.counts {
+            lines.push(format!("  {}: {}", chunk_type, count));
+        }
+        lines.join("\n")
+    }
+
+    /// The `--json` output: the same array of chunk objects as `print --json`.
+    pub fn to_json(&self) -> String {
+        chunks_json(&self.chunks)
+    }
+}
+
+/// Renders a byte count as `512 B`, `1.5 KiB`, `10.0 MiB` and so on.
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // compare the rounded value, so nothing is shown as 1024.0 of a unit
+    while (size * 10.0).round() >= 10240.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Summarizes the file size, chunk count and how many chunks of each type the file holds.
+pub fn stats(args: &StatsArgs) -> Result<Stats> {
+    let png = Png::from_file(&args.file_path)?;
+    let type_counts = png.type_counts();
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for chunk in &png {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !counts.iter().any(|(t, _)| *t == chunk_type) {
+            counts.push((chunk_type, type_counts[chunk.chunk_type()]));
+        }
+    }
+    Ok(Stats {
+        dimensions: png.dimensions().ok(),
+        file_size: png.total_size(),
+        data_size: png.data_size(),
+        animated: png.is_apng(),
+        frame_count: png.frame_count(),
+        chunk_count: png.chunk_count(),
+        counts,
+        chunks: chunk_infos(png.chunks()),
+    })
+}
+
+/// One chunk's metadata, as listed by `--json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    /// Where the chunk's length field starts in the file
+    pub offset: usize,
+    pub critical: bool,
+    pub safe_to_copy: bool,
+}
+
+impl ChunkInfo {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":{},\"length\":{},\"crc\":{},\"offset\":{},\"critical\":{},\"safe_to_copy\":{}}}",
+            json_string(&self.chunk_type),
+            self.length,
+            self.crc,
+            self.offset,
+            self.critical,
+            self.safe_to_copy
+        )
+    }
+}
+
+/// The metadata of every chunk, with its file offset.
+fn chunk_infos(chunks: &[Chunk]) -> Vec<ChunkInfo> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut infos = Vec::new();
+    for chunk in chunks {
+        infos.push(ChunkInfo {
+            chunk_type: chunk.chunk_type().to_string(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            offset,
+            critical: chunk.chunk_type().is_critical(),
+            safe_to_copy: chunk.chunk_type().is_safe_to_copy(),
+        });
+        offset += chunk.total_bytes();
+    }
+    infos
+}
+
+/// Serializes chunk metadata as a JSON array.
+fn chunks_json(chunks: &[ChunkInfo]) -> String {
+    let objects: Vec<String> = chunks.iter().map(ChunkInfo::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The stored and freshly computed crc of one chunk.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CrcCheck {
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+impl CrcCheck {
+    pub fn is_valid(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VerifyReport {
+    /// One check per chunk, in file order
+    pub chunks: Vec<CrcCheck>,
+    pub threshold: f64,
+}
+
+impl VerifyReport {
+    /// Indices of the chunks whose stored crc doesn't match their contents.
+    pub fn invalid(&self) -> Vec<usize> {
+        (0..self.chunks.len())
+            .filter(|&i| !self.chunks[i].is_valid())
+            .collect()
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.chunks.is_empty() {
+            0.0
+        } else {
+            self.invalid().len() as f64 * 100.0 / self.chunks.len() as f64
+        }
+    }
+
+    /// Fails when the share of bad chunks is above the threshold percent.
+    pub fn check(&self) -> Result<()> {
+        if self.percent() > self.threshold {
+            Err(format!("Invalid CRCs exceed the {}% threshold", self.threshold).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, check) in self.chunks.iter().enumerate() {
+            if check.is_valid() {
+                writeln!(f, "{}: {} ok", i, check.chunk_type)?;
+            } else {
+                writeln!(
+                    f,
+                    "{}: {} has an invalid CRC (stored {:08x}, computed {:08x})",
+                    i, check.chunk_type, check.stored, check.computed
+                )?;
+            }
+        }
+        write!(
+            f,
+            "{} of {} chunks have invalid CRC ({:.0}%)",
+            self.invalid().len(),
+            self.chunks.len(),
+            self.percent()
+        )
+    }
+}
+
+/// Compares every chunk's stored crc with one computed from its type and data.
+pub fn verify(args: &VerifyArgs) -> Result<VerifyReport> {
+    let png = Png::from_file_unchecked(&args.file_path)?;
+    let chunks = png
+        .chunks()
+        .iter()
+        .map(|x| CrcCheck {
+            chunk_type: x.chunk_type().clone(),
+            stored: x.crc(),
+            computed: x.computed_crc(),
+        })
+        .collect();
+    Ok(VerifyReport {
+        chunks,
+        threshold: args.threshold,
+    })
+}
+
+/// Loads the file without crc checks and writes it back with every crc recomputed.
+pub fn repair(args: &RepairArgs) -> Result<Report> {
+    let png = Png::from_file_unchecked(&args.file_path)?;
+    let repaired = png.chunks().iter().filter(|x| !x.verify_crc()).count();
+    let chunks: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .map(|x| Chunk::new(x.chunk_type().clone(), x.data().to_vec()))
+        .collect();
+
+    let summary = format!("Repaired {} chunk(s)", repaired);
+    save(
+        &Png::from_chunks(chunks),
+        &args.file_path,
+        &args.write,
+        &summary,
+    )
+}
+
+/// Returns the chunk type and data offset of every occurrence of the pattern.
+pub fn find(args: &FindArgs) -> Result<Vec<(ChunkType, usize)>> {
+    let png = Png::from_file(&args.file_path)?;
+    let mut found = Vec::new();
+    for chunk in &png {
+        for offset in match_offsets(chunk.data(), args.pattern.as_bytes(), args.ignore_case) {
+            found.push((chunk.chunk_type().clone(), offset));
+        }
+    }
+    Ok(found)
+}
+
+fn match_offsets(haystack: &[u8], needle: &[u8], ignore_case: bool) -> Vec<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return Vec::new();
+    }
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| {
+            if ignore_case {
+                window.eq_ignore_ascii_case(needle)
+            } else {
+                *window == needle
+            }
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Strips ancillary chunks other than the `--keep` types and returns how many bytes that saved.
+pub fn minify(args: &MinifyArgs) -> Result<Outcome<usize>> {
+    let mut png = Png::from_file(&args.file_path)?;
+    let before = png.total_size();
+    let keep: Vec<&str> = args.keep.iter().map(|x| x.as_str()).collect();
+    let removed = png.retain_critical(&keep);
+    let saved = before - png.total_size();
+
+    let summary = format!("Removed {} chunk(s), saving {} bytes", removed, saved);
+    let report = save(&png, &args.file_path, &args.write, &summary)?;
+    Ok(Outcome {
+        value: saved,
+        report,
+    })
+}
+
+/// Writes each chunk's data to `out_dir` as `<index>_<type>.bin` and returns the paths in order.
+///
+/// The index is zero-padded to the same width for every file so they sort in file order.
+pub fn export(args: &ExportArgs) -> Result<Vec<PathBuf>> {
+    let png = Png::from_file(&args.file_path)?;
+    fs::create_dir_all(&args.out_dir)?;
+    let width = png.chunks().len().to_string().len().max(3);
+    let mut files = Vec::new();
+    for (i, chunk) in png.chunks().iter().enumerate() {
+        let name = format!("{:0width$}_{}.bin", i, chunk.chunk_type(), width = width);
+        let path = args.out_dir.join(name);
+        fs::write(&path, chunk.data())?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// Reads the chunk files written by [`export`] back in index order and writes them as a PNG.
+///
+/// Each chunk's type comes from its file name and its crc is recomputed from the data, so an
+/// edited `.bin` file reassembles into a valid chunk.
+pub fn assemble(args: &AssembleArgs) -> Result<Report> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&args.in_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "bin") {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (index, chunk_type) = parse_export_name(&name)
+            .ok_or_else(|| format!("{} doesn't look like <index>_<type>.bin", name))?;
+        entries.push((index, chunk_type, path));
+    }
+    entries.sort_by_key(|(index, _, _)| *index);
+    for (expected, (index, _, path)) in entries.iter().enumerate() {
+        if *index != expected {
+            return Err(format!(
+                "Expected chunk {} but found {}, chunk files must be numbered from 0 without gaps",
+                expected,
+                path.display()
+            )
+            .into());
+        }
+    }
+
+    let mut chunks = Vec::with_capacity(entries.len());
+    for (_, chunk_type, path) in entries {
+        chunks.push(Chunk::new(chunk_type, fs::read(path)?));
+    }
+    let png = Png::from_chunks(chunks);
+    let summary = format!("Assembled {} chunk(s)", png.chunks().len());
+    save(&png, &args.output_file, &args.write, &summary)
+}
+
+/// Splits an export file name such as `001_IDAT.bin` into its index and chunk type.
+fn parse_export_name(name: &str) -> Option<(usize, ChunkType)> {
+    let (index, chunk_type) = name.strip_suffix(".bin")?.split_once('_')?;
+    // FromStr would quietly take the first four bytes of a longer name
+    if index.is_empty() || !index.bytes().all(|x| x.is_ascii_digit()) || chunk_type.len() != 4 {
+        return None;
+    }
+    Some((index.parse().ok()?, ChunkType::from_str(chunk_type).ok()?))
+}
+
+/// Writes a deterministic copy of the file to `output`.
+///
+/// Everything after `IEND` is dropped, every chunk is rebuilt from its type and data so stale
+/// crcs are recomputed, and runs of ancillary chunks are sorted with [`Png::canonical_order`].
+pub fn normalize(path: &Path, output: &Path, write: &WriteArgs) -> Result<Report> {
+    let png = Png::from_bytes_unchecked(&fs::read(path)?)?;
+    let chunks = png
+        .iter()
+        .map(|x| Chunk::new(x.chunk_type().clone(), x.data().to_vec()))
+        .collect();
+    let mut png = Png::from_chunks(chunks);
+    png.canonical_order();
+
+    let summary = format!("Normalized {}", path.display());
+    save(&png, output, write, &summary)
+}
+
+/// Sorts the ancillary chunks with [`Png::canonical_order`] and writes the file back, so files
+/// differing only in metadata order come out identical.
+pub fn canonicalize(args: &CanonicalizeArgs) -> Result<Report> {
+    let mut png = Png::from_file(&args.file_path)?;
+    png.canonical_order();
+    let summary = format!("Canonicalized {}", args.file_path.display());
+    save(&png, &args.file_path, &args.write, &summary)
+}
+
+/// Rebuilds every chunk from its type and data and compares the result with the file on disk.
+///
+/// Returns the offset of the first byte that differs, or `None` if the file round-trips exactly.
+/// Chunks are read without checking their crc or length, so a stale field shows up at the byte
+/// where it diverges. Reading stops after `IEND` or at the first chunk that cannot be read at
+/// all, and anything from there on counts as a difference.
+pub fn roundtrip_check(path: &Path) -> Result<Option<usize>> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(Png::STANDARD_HEADER) {
+        return Err(PngError::BadSignature.into());
+    }
+    let mut encoded = Png::STANDARD_HEADER.to_vec();
+    let mut offset = encoded.len();
+    while let Ok(chunk) = ChunkRef::parse_unchecked(&bytes[offset..]) {
+        encoded.extend(Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()).as_bytes());
+        offset += chunk.total_bytes();
+        if chunk.chunk_type().to_string() == "IEND" {
+            break;
+        }
+    }
+    Ok(bytes
+        .iter()
+        .zip(&encoded)
+        .position(|(a, b)| a != b)
+        .or((bytes.len() != encoded.len()).then(|| bytes.len().min(encoded.len()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+        Chunk::new(chunk_type, data.bytes().collect())
+    }
+
+    fn testing_png() -> Png {
+        let ihdr_data = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr_data),
+            chunk_from_strings("ruSt", "This is a secret message!"),
+            chunk_from_strings("IEND", ""),
+        ])
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme_{}_{}.png", std::process::id(), name))
+    }
+
+    fn swap_args(path: &Path, old_type: &str, new_type: &str) -> SwapArgs {
+        SwapArgs {
+            file_path: path.to_path_buf(),
+            old_type: String::from(old_type),
+            new_type: String::from(new_type),
+            ..Default::default()
+        }
+    }
+
+    fn temp_png(name: &str) -> PathBuf {
+        let path = temp_path(name);
+        testing_png().write_file(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_swap() {
+        let path = temp_png("swap");
+        let old_crc = testing_png().chunk_by_type("ruSt").unwrap().crc();
+
+        let report = swap(&swap_args(&path, "ruSt", "ruSp")).unwrap();
+        assert_eq!(report.lines(Level::Info), ["Changed chunk ruSt to ruSp"]);
+
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("ruSt").is_none());
+        let chunk = png.chunk_by_type("ruSp").unwrap();
+        assert_eq!(chunk.data_as_string().unwrap(), "This is a secret message!");
+        assert_ne!(chunk.crc(), old_crc);
+        assert_eq!(
+            chunk.crc(),
+            chunk_from_strings("ruSp", "This is a secret message!").crc()
+        );
+        // the chunk keeps its place before IEND
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "ruSp");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_swap_missing_type() {
+        let path = temp_png("swap_missing");
+        assert!(swap(&swap_args(&path, "noPe", "ruSp")).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_swap_invalid_new_type() {
+        let path = temp_png("swap_invalid");
+        assert!(swap(&swap_args(&path, "ruSt", "ru1t")).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = temp_png("merge_base");
+        let extra = temp_path("merge_extra");
+        let out = temp_path("merge_out");
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("abCd", "from extra"));
+        png.append_chunk(chunk_from_strings("tEXt", "public"));
+        png.append_chunk(chunk_from_strings("RuSt", "critical"));
+        png.write_file(&extra).unwrap();
+
+        let args = MergeArgs {
+            base: base.clone(),
+            extra: extra.clone(),
+            output_file: out.clone(),
+            ..Default::default()
+        };
+        // ruSt is already in the base, tEXt is public and RuSt is critical
+        assert_eq!(merge(&args).unwrap().value, 1);
+
+        let merged = Png::from_file(&out).unwrap();
+        merged.validate().unwrap();
+        let types: Vec<String> = merged
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "abCd", "IEND"]);
+        for path in [base, extra, out] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let ihdr_data = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        let mut paths = Vec::new();
+        for (i, order) in [["tEXt", "zzZz", "aaAa"], ["aaAa", "tEXt", "zzZz"]]
+            .iter()
+            .enumerate()
+        {
+            let mut chunks = vec![Chunk::new(
+                ChunkType::from_str("IHDR").unwrap(),
+                ihdr_data.clone(),
+            )];
+            chunks.extend(order.iter().map(|x| chunk_from_strings(x, "metadata")));
+            chunks.push(chunk_from_strings("IDAT", "pixels"));
+            chunks.push(chunk_from_strings("IEND", ""));
+            let path = temp_path(&format!("canonicalize_{}", i));
+            Png::from_chunks(chunks).write_file(&path).unwrap();
+            canonicalize(&CanonicalizeArgs {
+                file_path: path.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+            paths.push(path);
+        }
+        let first = std::fs::read(&paths[0]).unwrap();
+        assert_eq!(first, std::fs::read(&paths[1]).unwrap());
+        let types: Vec<String> = Png::from_bytes(&first)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "aaAa", "tEXt", "zzZz", "IDAT", "IEND"]);
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_replace_data() {
+        let path = temp_png("replace_data");
+        let mut args = ReplaceDataArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            message: String::from("A new message"),
+            ..Default::default()
+        };
+        replace_data(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.chunks().len(), testing_png().chunks().len());
+        let chunk = &png.chunks()[1];
+        assert_eq!(chunk.chunk_type().to_string(), "ruSt");
+        assert_eq!(chunk.data(), b"A new message");
+        assert_eq!(chunk, &chunk_from_strings("ruSt", "A new message"));
+
+        args.chunk_type = String::from("noPe");
+        let err = replace_data(&args).unwrap_err();
+        assert_eq!(err.to_string(), "No chunk of type noPe found");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_check_identical() {
+        let path = temp_png("roundtrip");
+        assert_eq!(roundtrip_check(&path).unwrap(), None);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let len = bytes.len();
+        bytes.extend_from_slice(b"trailing");
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(roundtrip_check(&path).unwrap(), Some(len));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_check_stale_crc() {
+        let mut bytes = testing_png().as_bytes();
+        // the last crc byte of the ruSt chunk, which ends 12 bytes before the end of the file
+        let at = bytes.len() - 12 - 1;
+        bytes[at] ^= 0xff;
+
+        let path = temp_path("roundtrip_stale_crc");
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(roundtrip_check(&path).unwrap(), Some(at));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_check_stale_length() {
+        let mut bytes = testing_png().as_bytes();
+        // the ruSt chunk starts after the signature and the 25 byte IHDR chunk
+        let offset = 8 + 25;
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        bytes[offset..offset + 4].copy_from_slice(&(length + 4).to_be_bytes());
+
+        let path = temp_path("roundtrip_stale");
+        std::fs::write(&path, &bytes).unwrap();
+        // the chunk now takes its old crc as data and the next chunk's length field as its crc,
+        // so the first difference is inside that field
+        let crc_at = offset + 8 + length as usize + 4;
+        let data = &bytes[offset + 8..crc_at];
+        let crc = Chunk::new(ChunkType::from_str("ruSt").unwrap(), data.to_vec()).crc();
+        let diverges = (0..4)
+            .find(|&i| bytes[crc_at + i] != crc.to_be_bytes()[i])
+            .unwrap();
+        assert_eq!(roundtrip_check(&path).unwrap(), Some(crc_at + diverges));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_plan() {
+        let plan = plan(1000, 300).unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                chunks: 4,
+                overhead: 48
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_zero_chunk_size() {
+        assert!(plan(1000, 0).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let path = temp_png("encode");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message: Some(String::from("hidden")),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(
+            png.chunk_by_type("teSt").unwrap().data_as_string().unwrap(),
+            "hidden"
+        );
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_appends_existing_type() {
+        let path = temp_png("encode_append");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            message: Some(String::from("second")),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let count = png
+            .chunks()
+            .iter()
+            .filter(|x| x.chunk_type().to_string() == "ruSt")
+            .count();
+        assert_eq!(count, 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_overwrite() {
+        let path = temp_png("encode_overwrite");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            message: Some(String::from("replacement")),
+            overwrite: true,
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        let chunk = &png.chunks()[1];
+        assert_eq!(chunk.data_as_string().unwrap(), "replacement");
+        assert_eq!(chunk.crc(), chunk_from_strings("ruSt", "replacement").crc());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_message_size_warning() {
+        assert_eq!(message_size_warning(10, DEFAULT_MAX_MESSAGE_SIZE), None);
+        let warning = message_size_warning(2 << 20, DEFAULT_MAX_MESSAGE_SIZE);
+        assert!(warning.unwrap().contains("--max-size"));
+        assert_eq!(message_size_warning(u32::MAX as u64 + 1, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_check_chunk_len() {
+        assert!(check_chunk_len(0).is_ok());
+        assert!(check_chunk_len(Chunk::MAX_LENGTH as u64).is_ok());
+
+        let err = check_chunk_len(Chunk::MAX_LENGTH as u64 + 1).unwrap_err();
+        assert!(err.to_string().contains("at most 2147483647 bytes"));
+        assert!(check_chunk_len(u32::MAX as u64 + 1).is_err());
+        // whatever encode writes, the default reader accepts
+        assert_eq!(Png::DEFAULT_MAX_CHUNK_LEN, Chunk::MAX_LENGTH);
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(
+            split_message(b"abcdefg", 3).unwrap(),
+            vec![b"abc".to_vec(), b"def".to_vec(), b"g".to_vec()]
+        );
+        assert_eq!(split_message(b"", 3).unwrap(), vec![Vec::<u8>::new()]);
+        assert!(split_message(b"abc", 0).is_err());
+        let pieces = split_message(&[0; 1000], 300).unwrap();
+        assert_eq!(pieces.len(), plan(1000, 300).unwrap().chunks);
+    }
+
+    #[test]
+    fn test_encode_split_round_trip() {
+        let path = temp_png("encode_split");
+        let payload: Vec<u8> = (0..10 * 1024).map(|x| (x % 251) as u8).collect();
+        let message_path = temp_path("encode_split_payload");
+        std::fs::write(&message_path, &payload).unwrap();
+
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("spLt")),
+            message_file: Some(message_path.clone()),
+            split: Some(1024),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let pieces = png
+            .chunks()
+            .iter()
+            .filter(|x| x.chunk_type().to_string() == "spLt")
+            .count();
+        assert_eq!(pieces, 10);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        let out = temp_path("encode_split_out");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("spLt")),
+            out: Some(out.clone()),
+            join: true,
+            ..Default::default()
+        };
+        decode(&args).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), payload);
+
+        for path in [path, message_path, out] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_base64() {
+        let path = temp_png("encode_base64");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message: Some(String::from("AP+AAQ==")),
+            base64: true,
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.chunk_by_type("teSt").unwrap().data(), &[0, 255, 128, 1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_invalid_base64() {
+        let path = temp_png("encode_invalid_base64");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message: Some(String::from("not base64!")),
+            base64: true,
+            ..Default::default()
+        };
+        let err = encode(&args).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid base64 message"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_out() {
+        let path = temp_path("decode_out");
+        let mut png = testing_png();
+        let chunk_type = ChunkType::from_str("biNy").unwrap();
+        png.append_chunk(Chunk::new(chunk_type, vec![0, 159, 146, 150, 255]));
+        png.write_file(&path).unwrap();
+
+        let out = temp_path("decode_out_message");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("biNy")),
+            out: Some(out.clone()),
+            ..Default::default()
+        };
+        decode(&args).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), vec![0, 159, 146, 150, 255]);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn test_decode_join() {
+        let path = temp_path("decode_join");
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", " More"));
+        png.write_file(&path).unwrap();
+
+        let out = temp_path("decode_join_message");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            out: Some(out.clone()),
+            join: true,
+            ..Default::default()
+        };
+        decode(&args).unwrap();
+        assert_eq!(
+            std::fs::read(&out).unwrap(),
+            b"This is a secret message! More"
+        );
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn test_encode_critical_private_type() {
+        let path = temp_png("critical_private");
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("RuSt")),
+            message: Some(String::from("risky")),
+            strict: true,
+            ..Default::default()
+        };
+        assert!(encode(&args).is_err());
+        assert!(Png::from_file(&path)
+            .unwrap()
+            .chunk_by_type("RuSt")
+            .is_none());
+
+        args.strict = false;
+        encode(&args).unwrap();
+        assert!(Png::from_file(&path)
+            .unwrap()
+            .chunk_by_type("RuSt")
+            .is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_twice_is_idempotent() {
+        let path = temp_png("encode_twice");
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            message: Some(String::from("same again")),
+            ..Default::default()
+        };
+        let count = |path: &Path| {
+            Png::from_file(path)
+                .unwrap()
+                .chunks()
+                .iter()
+                .filter(|x| x.data() == b"same again")
+                .count()
+        };
+        encode(&args).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        args.write.backup = Some(BackupMode::Simple);
+        let report = encode(&args).unwrap();
+        assert_eq!(
+            report.lines(Level::Info),
+            ["Message already present in a ruSt chunk, not adding it again"]
+        );
+        assert_eq!(count(&path), 1);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+        assert!(!backup_path(&path, BackupMode::Simple).exists());
+        args.write.backup = None;
+
+        args.allow_duplicate = true;
+        encode(&args).unwrap();
+        assert_eq!(count(&path), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_type_hex() {
+        assert_eq!(parse_type_hex("52755374").unwrap(), *b"RuSt");
+        assert_eq!(parse_type_hex("ff00aBcD").unwrap(), [0xff, 0, 0xab, 0xcd]);
+        for bad in [
+            "5275537",
+            "527553740",
+            "5275537g",
+            "+2755374",
+            "",
+            "52 75 53 74",
+        ] {
+            assert!(parse_type_hex(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_encode_type_hex() {
+        let path = temp_png("encode_type_hex");
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("by hex")),
+            type_hex: Some(String::from("68655874")),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.chunk_by_type("heXt").unwrap().data(), b"by hex");
+
+        // invalid types need --force
+        args.type_hex = Some(String::from("68653174"));
+        assert!(encode(&args).is_err());
+        args.force = true;
+        encode(&args).unwrap();
+        let png = Png::from_file_unchecked(&path);
+        assert!(png.is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_random_type() {
+        let path = temp_png("encode_random_type");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("Pick a type for me")),
+            random_type: true,
+            seed: Some(42),
+            ..Default::default()
+        };
+        let report = encode(&args).unwrap();
+
+        let chunk_type = ChunkType::random_private(42);
+        assert_eq!(
+            report.lines(Level::Output),
+            [format!("Chunk type: {}", chunk_type)]
+        );
+        let png = Png::from_file(&path).unwrap();
+        let chunk = png.chunk_by_type(&chunk_type.to_string()).unwrap();
+        assert_eq!(chunk.data(), b"Pick a type for me");
+
+        let args = EncodeArgs {
+            message: Some(String::from("both")),
+            ..args
+        };
+        assert!(encode(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_only_inserts_the_new_chunk() {
+        let path = temp_path("encode_byte_diff");
+        // ancillary chunks in odd places, which a normalizing writer would move
+        let ihdr_data = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        let original = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr_data),
+            chunk_from_strings("zzZz", "sorts last"),
+            chunk_from_strings("IDAT", "pixels"),
+            chunk_from_strings("aaAa", "sorts first"),
+            chunk_from_strings("IDAT", "more pixels"),
+            chunk_from_strings("IEND", ""),
+        ])
+        .as_bytes();
+        std::fs::write(&path, &original).unwrap();
+
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            message: Some(String::from("inserted")),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let iend_offset = original.len() - 12;
+        let mut expected = original[..iend_offset].to_vec();
+        expected.extend(chunk_from_strings("ruSt", "inserted").as_bytes());
+        expected.extend(&original[iend_offset..]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_formats() {
+        let path = temp_png("encode_formats");
+        for (format, message) in [
+            (MessageFormat::Text, "Hello viewers"),
+            (MessageFormat::Ztxt, "squeezed"),
+            (MessageFormat::Itxt, "Grüße, 世界"),
+        ] {
+            // the chunk type is implied, so the one positional is the message
+            let args = EncodeArgs {
+                file_path: path.clone(),
+                chunk_type: Some(String::from(message)),
+                format,
+                keyword: Some(String::from("Comment")),
+                ..Default::default()
+            };
+            encode(&args).unwrap();
+
+            let args = DecodeArgs {
+                file_path: path.clone(),
+                format: Some(format),
+                ..Default::default()
+            };
+            assert_eq!(decode(&args).unwrap().value.as_deref(), Some(message));
+        }
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("tEXt").unwrap().as_text().is_some());
+        assert!(png.chunk_by_type("zTXt").unwrap().as_ztxt().is_ok());
+        assert!(png.chunk_by_type("iTXt").unwrap().as_itxt().is_ok());
+        assert!(png.validate().is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_compressed_itxt() {
+        let path = temp_png("encode_compressed_itxt");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            message: Some("Grüße aus Köln ".repeat(20)),
+            format: MessageFormat::Itxt,
+            keyword: Some(String::from("Comment")),
+            language: Some(String::from("de")),
+            translated_keyword: Some(String::from("Kommentar")),
+            compress: true,
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let itxt = png.chunk_by_type("iTXt").unwrap().as_itxt().unwrap();
+        assert!(itxt.compressed);
+        assert_eq!(itxt.language, "de");
+        assert_eq!(itxt.translated_keyword, "Kommentar");
+        assert_eq!(itxt.text, args.message.unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_format_checks() {
+        let path = temp_png("encode_format_checks");
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            message: Some(String::from("Hello viewers")),
+            format: MessageFormat::Text,
+            keyword: Some(String::from("Comment")),
+            ..Default::default()
+        };
+        let err = encode(&args).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--format text stores a tEXt chunk, not ruSt"
+        );
+
+        args.chunk_type = Some(String::from("tEXt"));
+        args.keyword = None;
+        assert!(encode(&args).is_err());
+
+        args.keyword = Some(String::from("Comment"));
+        args.format = MessageFormat::Text;
+        args.message = Some(String::from("Grüße, 世界"));
+        // tEXt is latin-1 only, iTXt is the format for this
+        assert!(encode(&args).is_err());
+
+        args.message = Some(String::from("Hello viewers"));
+        args.compress = true;
+        assert!(encode(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_envelope() {
+        let path = temp_png("encode_envelope");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("enVl")),
+            message: Some(String::from("wrapped")),
+            envelope: true,
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let data = png.chunk_by_type("enVl").unwrap().data();
+        assert_eq!(&data[..4], &envelope::MAGIC);
+
+        let out = temp_path("encode_envelope_out");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("enVl")),
+            out: Some(out.clone()),
+            ..Default::default()
+        };
+        decode(&args).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"wrapped");
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn test_prompt_encode() {
+        let args = EncodeArgs {
+            file_path: PathBuf::from("image.png"),
+            ..Default::default()
+        };
+        let mut input = &b"12\nruSt\nhello there\n"[..];
+        let mut output = Vec::new();
+        let prompted = prompt_encode(&args, &mut input, &mut output).unwrap();
+        assert_eq!(prompted.chunk_type.as_deref(), Some("ruSt"));
+        assert_eq!(prompted.message.as_deref(), Some("hello there"));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Chunk type: \"12\" is not a valid chunk type, use four letters\n\
+             Chunk type: Message: "
+        );
+
+        // only the missing message is asked for
+        let args = EncodeArgs {
+            chunk_type: Some(String::from("ruSt")),
+            ..args
+        };
+        let mut output = Vec::new();
+        let prompted = prompt_encode(&args, &mut &b"hi\n"[..], &mut output).unwrap();
+        assert_eq!(prompted.message.as_deref(), Some("hi"));
+        assert_eq!(output, b"Message: ");
+
+        let err = prompt_encode(&args, &mut &b""[..], &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No message given");
+    }
+
+    #[test]
+    fn test_passphrase_sources() {
+        let env = |name: &str| (name == PASSPHRASE_VAR).then(|| String::from("from env"));
+        let no_env = |_: &str| None;
+        let no_prompt = |_: &str| -> Result<String> { panic!("should not prompt") };
+
+        assert_eq!(
+            passphrase(Some("given"), env, no_prompt, true).unwrap(),
+            "given"
+        );
+        assert_eq!(passphrase(None, env, no_prompt, true).unwrap(), "from env");
+
+        let mut answers = vec!["typed", "typed"].into_iter();
+        let prompt = |_: &str| Ok(answers.next().unwrap().to_string());
+        assert_eq!(passphrase(None, no_env, prompt, true).unwrap(), "typed");
+
+        let mut answers = vec!["typed", "typo"].into_iter();
+        let prompt = |_: &str| Ok(answers.next().unwrap().to_string());
+        let err = passphrase(None, no_env, prompt, true).unwrap_err();
+        assert_eq!(err.to_string(), "Passphrases don't match");
+
+        // decode asks only once
+        let mut asked = 0;
+        let prompt = |_: &str| {
+            asked += 1;
+            Ok(String::from("typed"))
+        };
+        assert_eq!(passphrase(None, no_env, prompt, false).unwrap(), "typed");
+        assert_eq!(asked, 1);
+    }
+
+    #[test]
+    fn test_info() {
+        assert_eq!(
+            info("ruSt").unwrap(),
+            [
+                "ruSt",
+                "  ancillary: decoders that don't recognize it can ignore it",
+                "  private: an application's own type, unknown to other decoders",
+                "  reserved bit valid: the third letter is uppercase, as the spec requires",
+                "  safe to copy: editors may keep it even after changing critical chunks",
+            ]
+        );
+        let lines = info("IHDR").unwrap();
+        assert!(lines[1].starts_with("  critical"));
+        assert!(lines[4].starts_with("  unsafe to copy"));
+
+        for bad in ["ru1t", "ruS", "ruStt", ""] {
+            let err = info(bad).unwrap_err();
+            assert!(
+                err.to_string().contains("use four ASCII letters"),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk_meta() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("ruSt").unwrap();
+        let meta = chunk_meta(chunk);
+        assert_eq!(
+            meta[..5],
+            [
+                "Type: ruSt",
+                "Critical: false",
+                "Public: false",
+                "Reserved bit valid: true",
+                "Safe to copy: true",
+            ]
+        );
+        assert_eq!(meta[5], format!("CRC: {}", chunk.crc_hex()));
+    }
+
+    #[test]
+    fn test_encode_transforms() {
+        let path = temp_png("encode_transforms");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("trNs")),
+            message: Some(String::from("compressed, encrypted and armored")),
+            compress: true,
+            encrypt: Some(Some(String::from("hunter2"))),
+            armor: true,
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let data = png.chunk_by_type("trNs").unwrap().data();
+        let (content_type, _) = envelope::unwrap(data).unwrap().unwrap();
+        assert_eq!(content_type, ContentType::Transformed);
+
+        let mut args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("trNs")),
+            ..Default::default()
+        };
+        assert!(decode(&args).is_err());
+        args.passphrase = Some(Some(String::from("hunter2")));
+        let message = decode(&args).unwrap().value;
+        assert_eq!(
+            message.as_deref(),
+            Some("compressed, encrypted and armored")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_dir() {
+        let dir = std::env::temp_dir().join(format!("pngme_{}_encode_dir", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        testing_png().write_file(dir.join("a.png")).unwrap();
+        testing_png().write_file(dir.join("b.PNG")).unwrap();
+        std::fs::write(dir.join("broken.png"), "not a png").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let args = EncodeDirArgs {
+            dir: dir.clone(),
+            chunk_type: String::from("diRs"),
+            message: String::from("batch"),
+            ..Default::default()
+        };
+        let outcome = encode_dir(&args).unwrap();
+        assert_eq!(outcome.value, 1);
+        let info = outcome.report.lines(Level::Info);
+        // each file's own summary, a progress line per file and the total
+        assert_eq!(info.len(), 2 + 3 + 1);
+        assert_eq!(info.last(), Some(&"Encoded 2 of 3 files"));
+        let errors = outcome.report.lines(Level::Error);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.png"));
+
+        for name in ["a.png", "b.PNG"] {
+            let png = Png::from_file(dir.join(name)).unwrap();
+            assert_eq!(png.chunk_by_type("diRs").unwrap().data(), b"batch");
+        }
+        assert_eq!(std::fs::read(dir.join("notes.txt")).unwrap(), b"ignored");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_added_chunk() {
+        let old = testing_png();
+        let mut new = testing_png();
+        new.append_chunk(chunk_from_strings("ruSx", "extra"));
+        assert_eq!(
+            diff_chunks(old.chunks(), new.chunks()),
+            ["+ 2: ruSx (5 bytes)"]
+        );
+        assert!(diff_chunks(old.chunks(), old.chunks()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_removed_and_modified() {
+        let old = testing_png();
+        let mut new = testing_png();
+        new.replace_first_chunk("ruSt", chunk_from_strings("ruSt", "changed"))
+            .unwrap();
+        assert_eq!(
+            diff_chunks(old.chunks(), new.chunks()),
+            ["~ 1: ruSt (25 -> 7 bytes)"]
+        );
+
+        new.remove_first_chunk("ruSt").unwrap();
+        assert_eq!(
+            diff_chunks(old.chunks(), new.chunks()),
+            ["- 1: ruSt (25 bytes)"]
+        );
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let old = temp_png("diff_old");
+        let new = temp_png("diff_new");
+        let args = EncodeArgs {
+            file_path: new.clone(),
+            chunk_type: Some(String::from("ruSx")),
+            message: Some(String::from("extra")),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+        let args = DiffArgs {
+            old_file: old.clone(),
+            new_file: new.clone(),
+        };
+        assert_eq!(diff(&args).unwrap(), ["+ 2: ruSx (5 bytes)"]);
+        std::fs::remove_file(old).unwrap();
+        std::fs::remove_file(new).unwrap();
+    }
+
+    #[test]
+    fn test_decode_auto() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("biNy").unwrap(),
+            vec![0xff, 0x00],
+        ));
+        png.append_chunk(Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![0]));
+        png.append_chunk(chunk_from_strings("noTe", "second"));
+        assert_eq!(
+            auto_candidates(&png),
+            [
+                (
+                    String::from("ruSt"),
+                    String::from("This is a secret message!")
+                ),
+                (String::from("noTe"), String::from("second")),
+            ]
+        );
+
+        let path = temp_png("decode_auto");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            auto: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            decode(&args).unwrap().value.unwrap(),
+            "ruSt: This is a secret message!"
+        );
+
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            ..Default::default()
+        };
+        remove(&args).unwrap();
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            auto: true,
+            ..Default::default()
+        };
+        assert!(decode(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_flagged_type() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let args = SetFlagsArgs {
+            safe_to_copy: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            flagged_type(&chunk_type, &args).unwrap().to_string(),
+            "ruST"
+        );
+
+        let args = SetFlagsArgs {
+            critical: Some(true),
+            public: Some(true),
+            reserved_valid: Some(true),
+            safe_to_copy: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            flagged_type(&chunk_type, &args).unwrap().to_string(),
+            "RUSt"
+        );
+        assert_eq!(
+            flagged_type(&chunk_type, &SetFlagsArgs::default()).unwrap(),
+            chunk_type
+        );
+    }
+
+    #[test]
+    fn test_set_flags() {
+        let path = temp_png("set_flags");
+        let args = SetFlagsArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            safe_to_copy: Some(false),
+            ..Default::default()
+        };
+        set_flags(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let chunk = &png.chunks()[1];
+        assert_eq!(chunk.chunk_type().to_string(), "ruST");
+        assert!(!chunk.chunk_type().is_safe_to_copy());
+        assert_eq!(chunk.data(), b"This is a secret message!");
+        assert!(set_flags(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_chunk_line() {
+        let png = testing_png();
+        assert_eq!(
+            chunk_line(0, &png.chunks()[0], false),
+            format!(
+                "0: IHDR (13 bytes, critical, crc {})",
+                png.chunks()[0].crc_hex()
+            )
+        );
+        assert!(chunk_line(1, &png.chunks()[1], true).contains("\x1b[32mruSt\x1b[0m"));
+
+        let mut bytes = png.chunks()[1].as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let corrupt = Chunk::try_from_unchecked(&bytes).unwrap();
+        let line = chunk_line(1, &corrupt, true);
+        assert!(line.contains("\x1b[31mruSt\x1b[0m"));
+        assert!(line.ends_with(", invalid crc)"));
+    }
+
+    #[test]
+    fn test_decode_returns_message() {
+        let path = temp_png("decode_returns");
+        let mut args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("ruSt")),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode(&args).unwrap().value,
+            Some(String::from("This is a secret message!"))
+        );
+
+        args.base64 = true;
+        assert_eq!(
+            decode(&args).unwrap().value,
+            Some(BASE64.encode("This is a secret message!"))
+        );
+
+        let out = temp_path("decode_returns_out");
+        args.base64 = false;
+        args.out = Some(out.clone());
+        assert_eq!(decode(&args).unwrap().value, None);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let path = temp_png("stats");
+        let args = StatsArgs {
+            file_path: path.clone(),
+            ..Default::default()
+        };
+        let stats = stats(&args).unwrap();
+        assert_eq!(stats.dimensions, Some((1, 1)));
+        assert_eq!(stats.file_size, testing_png().total_size());
+        assert_eq!(stats.data_size, 13 + 25);
+        assert!(!stats.animated);
+        assert_eq!(stats.chunk_count, 3);
+        assert_eq!(
+            stats.counts,
+            [
+                (String::from("IHDR"), 1),
+                (String::from("ruSt"), 1),
+                (String::from("IEND"), 1)
+            ]
+        );
+        let report = stats.report(true);
+        assert!(report.starts_with("Dimensions: 1x1\n"));
+        assert!(report.contains(&format!("File size: {} bytes", stats.file_size)));
+        assert!(stats.report(false).contains("Data size: 38 B"));
+        assert_eq!(
+            stats.chunks[1],
+            ChunkInfo {
+                chunk_type: String::from("ruSt"),
+                length: 25,
+                crc: testing_png().chunks()[1].crc(),
+                offset: 8 + 12 + 13,
+                critical: false,
+                safe_to_copy: true,
+            }
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+        let chunks = json.as_array().unwrap();
+        assert_eq!(chunks.len(), 3);
+        for (chunk, expected) in chunks.iter().zip(testing_png().chunks()) {
+            let keys: Vec<_> = chunk
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect();
+            assert_eq!(
+                keys,
+                [
+                    "crc",
+                    "critical",
+                    "length",
+                    "offset",
+                    "safe_to_copy",
+                    "type"
+                ]
+            );
+            assert_eq!(chunk["type"], expected.chunk_type().to_string());
+            assert_eq!(chunk["crc"], expected.crc());
+        }
+        assert_eq!(chunks[2]["offset"], stats.file_size - 12);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1024), "1.0 KiB");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(1048575), "1.0 MiB");
+        assert_eq!(human_size(1048524), "1023.9 KiB");
+        assert_eq!(human_size(1048576), "1.0 MiB");
+        assert_eq!(human_size(10 * 1024 * 1024), "10.0 MiB");
+        assert_eq!(human_size(1 << 30), "1.0 GiB");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("ruSt", "ruSt"));
+        assert!(!glob_match("ruSt", "ruSx"));
+        assert!(!glob_match("ruS", "ruSt"));
+        assert!(glob_match("ruS?", "ruSt"));
+        assert!(glob_match("?u?t", "ruSt"));
+        assert!(!glob_match("ruS?", "ruS"));
+        assert!(glob_match("t*", "tEXt"));
+        assert!(glob_match("*", "IDAT"));
+        assert!(glob_match("*T", "IDAT"));
+        assert!(glob_match("I*A*", "IDAT"));
+        assert!(!glob_match("t*", "IDAT"));
+        assert!(!glob_match("*X", "IDAT"));
+    }
+
+    #[test]
+    fn test_print_filter() {
+        let path = temp_png("print_filter");
+        let args = PrintArgs {
+            file_path: path.clone(),
+            no_color: true,
+            filter: Some(String::from("I*")),
+            ..Default::default()
+        };
+        let listing = print(&args).unwrap();
+        assert_eq!(listing.lines.len(), 3);
+        assert!(listing.lines[1].starts_with("0: IHDR"));
+        assert!(listing.lines[2].starts_with("2: IEND"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_print_listing() {
+        let path = temp_png("print_listing");
+        let mut args = PrintArgs {
+            file_path: path.clone(),
+            no_color: true,
+            lint: true,
+            ..Default::default()
+        };
+        let listing = print(&args).unwrap();
+        assert_eq!(listing.lines.len(), 4);
+        assert_eq!(listing.lines[0], "1x1");
+        assert!(listing.lines[2].starts_with("1: ruSt (25 bytes, ancillary"));
+        assert!(listing.warnings.is_empty());
+
+        args.messages = true;
+        let listing = print(&args).unwrap();
+        assert!(listing
+            .lines
+            .contains(&String::from("ruSt: This is a secret message!")));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_minify() {
+        let path = temp_path("minify");
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("teXt", "kept"));
+        png.write_file(&path).unwrap();
+
+        let args = MinifyArgs {
+            file_path: path.clone(),
+            keep: vec![String::from("teXt")],
+            ..Default::default()
+        };
+        let saved = minify(&args).unwrap().value;
+        assert_eq!(saved, 12 + 25);
+
+        let png = Png::from_file(&path).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "teXt", "IEND"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_export() {
+        let path = temp_png("export");
+        let out_dir = std::env::temp_dir().join(format!("pngme_{}_export", std::process::id()));
+        let args = ExportArgs {
+            file_path: path.clone(),
+            out_dir: out_dir.join("nested"),
+        };
+        let files = export(&args).unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&args.out_dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["000_IHDR.bin", "001_ruSt.bin", "002_IEND.bin"]);
+        assert_eq!(files.len(), 3);
+        assert_eq!(
+            std::fs::read(&files[1]).unwrap(),
+            b"This is a secret message!"
+        );
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_export_name() {
+        let (index, chunk_type) = parse_export_name("012_ruSt.bin").unwrap();
+        assert_eq!(index, 12);
+        assert_eq!(chunk_type.to_string(), "ruSt");
+        assert!(parse_export_name("ruSt.bin").is_none());
+        assert!(parse_export_name("_ruSt.bin").is_none());
+        assert!(parse_export_name("001_ruSty.bin").is_none());
+        assert!(parse_export_name("001_ru5t.bin").is_none());
+        assert!(parse_export_name("001_ruSt.txt").is_none());
+    }
+
+    #[test]
+    fn test_export_assemble_round_trip() {
+        let path = temp_png("assemble");
+        let dir = std::env::temp_dir().join(format!("pngme_{}_assemble", std::process::id()));
+        let args = ExportArgs {
+            file_path: path.clone(),
+            out_dir: dir.clone(),
+        };
+        export(&args).unwrap();
+
+        let output = temp_path("assemble_out");
+        let args = AssembleArgs {
+            in_dir: dir.clone(),
+            output_file: output.clone(),
+            ..Default::default()
+        };
+        assemble(&args).unwrap();
+        assert_eq!(
+            std::fs::read(&output).unwrap(),
+            std::fs::read(&path).unwrap()
+        );
+
+        // a gap in the numbering is refused
+        std::fs::remove_file(dir.join("001_ruSt.bin")).unwrap();
+        assert!(assemble(&args).is_err());
+        for path in [path, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_decode_missing_chunk() {
+        let path = temp_png("decode_missing");
+        let args = DecodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("noPe")),
+            ..Default::default()
+        };
+        assert!(decode(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_no_validate() {
+        let path = temp_path("encode_no_validate");
+        let mut png = testing_png();
+        png.remove_first_chunk("IEND").unwrap();
+        png.write_file_unchecked(&path).unwrap();
+
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message: Some(String::from("hidden")),
+            ..Default::default()
+        };
+        assert!(encode(&args).is_err());
+
+        args.write.no_validate = true;
+        encode(&args).unwrap();
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("teSt").is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_flags_corrupt_chunk() {
+        let png = testing_png();
+        let mut bytes = png.as_bytes();
+        let secret_end = 8 + 25 + png.chunks()[1].total_bytes();
+        bytes[secret_end - 1] ^= 0xff;
+        let path = temp_path("verify_corrupt");
+        std::fs::write(&path, bytes).unwrap();
+
+        let args = VerifyArgs {
+            file_path: path.clone(),
+            threshold: 0.0,
+        };
+        let report = verify(&args).unwrap();
+        assert_eq!(report.chunks.len(), 3);
+        assert!(report.chunks[0].is_valid());
+        assert!(!report.chunks[1].is_valid());
+        assert_eq!(report.chunks[1].chunk_type.to_string(), "ruSt");
+        assert_eq!(report.chunks[1].computed, png.chunks()[1].crc());
+        assert_eq!(report.chunks[1].stored, png.chunks()[1].crc() ^ 0xff);
+        assert!(report.check().is_err());
+
+        let text = report.to_string();
+        assert!(text.starts_with("0: IHDR ok\n1: ruSt has an invalid CRC (stored "));
+        assert!(text.ends_with("1 of 3 chunks have invalid CRC (33%)"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_threshold() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("teSt", "another"));
+        let mut bytes = png.as_bytes();
+        // corrupt the last crc byte of the ruSt chunk, one of four chunks
+        let secret_end = 8 + 25 + png.chunks()[1].total_bytes();
+        bytes[secret_end - 1] ^= 0xff;
+
+        let path = temp_path("verify_threshold");
+        std::fs::write(&path, bytes).unwrap();
+        let mut args = VerifyArgs {
+            file_path: path.clone(),
+            threshold: 50.0,
+        };
+        let report = verify(&args).unwrap();
+        assert_eq!(report.invalid(), [1]);
+        assert_eq!(report.percent(), 25.0);
+        assert!(report.check().is_ok());
+
+        args.threshold = 10.0;
+        assert!(verify(&args).unwrap().check().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_equivalent_files() {
+        let ihdr = || Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]);
+        let first = Png::from_chunks(vec![
+            ihdr(),
+            chunk_from_strings("tEXt", "Comment\0hello"),
+            chunk_from_strings("ruSt", "secret"),
+            chunk_from_strings("IDAT", "one"),
+            chunk_from_strings("IDAT", "two"),
+            chunk_from_strings("IEND", ""),
+        ]);
+        let second = Png::from_chunks(vec![
+            ihdr(),
+            chunk_from_strings("ruSt", "secret"),
+            chunk_from_strings("IDAT", "one"),
+            chunk_from_strings("IDAT", "two"),
+            chunk_from_strings("tEXt", "Comment\0hello"),
+            chunk_from_strings("IEND", ""),
+        ]);
+
+        let first_path = temp_path("normalize_first");
+        let second_path = temp_path("normalize_second");
+        first.write_file(&first_path).unwrap();
+        let mut second_bytes = second.as_bytes();
+        second_bytes.extend_from_slice(b"trailing garbage");
+        std::fs::write(&second_path, second_bytes).unwrap();
+
+        let first_out = temp_path("normalize_first_out");
+        let second_out = temp_path("normalize_second_out");
+        normalize(&first_path, &first_out, &WriteArgs::default()).unwrap();
+        normalize(&second_path, &second_out, &WriteArgs::default()).unwrap();
+
+        let normalized = std::fs::read(&first_out).unwrap();
+        assert_eq!(normalized, std::fs::read(&second_out).unwrap());
+        let png = Png::try_from(normalized.as_slice()).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "ruSt", "tEXt", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[3].data_as_string().unwrap(), "one");
+
+        for path in [first_path, second_path, first_out, second_out] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_normalize_recomputes_crcs() {
+        let path = temp_png("normalize_crc");
+        let mut bytes = std::fs::read(&path).unwrap();
+        // flip the last byte of the ruSt crc
+        let png = Png::from_bytes(&bytes).unwrap();
+        let end = 8 + png.chunks()[..2]
+            .iter()
+            .map(|x| x.total_bytes())
+            .sum::<usize>();
+        bytes[end - 1] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Png::from_file(&path).is_err());
+
+        let out = temp_path("normalize_crc_out");
+        let dry_run = WriteArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+        normalize(&path, &out, &dry_run).unwrap();
+        assert!(!out.exists());
+
+        normalize(&path, &out, &WriteArgs::default()).unwrap();
+        let png = Png::from_file(&out).unwrap();
+        assert_eq!(
+            png.chunk_by_type("ruSt").unwrap().data(),
+            b"This is a secret message!"
+        );
+        for path in [path, out] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let path = temp_png("remove");
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            ..Default::default()
+        };
+        let Outcome {
+            value: removed,
+            report,
+        } = remove(&args).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data(), b"This is a secret message!");
+        // the confirmation stays off stdout
+        assert!(report.lines(Level::Info).is_empty());
+        assert_eq!(
+            report.lines(Level::Notice),
+            ["Removed 1 chunk(s) of type ruSt (25 bytes)"]
+        );
+
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("ruSt").is_none());
+        assert!(remove(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let path = temp_path("remove_all");
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "second"));
+        png.append_chunk(chunk_from_strings("ruSt", "third"));
+        png.write_file(&path).unwrap();
+
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            all: true,
+            ..Default::default()
+        };
+        assert_eq!(remove(&args).unwrap().value.len(), 3);
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert!(remove(&args).unwrap().value.is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_nth() {
+        let path = temp_path("remove_nth");
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "second"));
+        png.append_chunk(chunk_from_strings("ruSt", "third"));
+        png.write_file(&path).unwrap();
+
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            nth: Some(1),
+            ..Default::default()
+        };
+        let removed = remove(&args).unwrap().value;
+        assert_eq!(removed[0].data(), b"second");
+        let png = Png::from_file(&path).unwrap();
+        let data: Vec<&[u8]> = png.chunks_by_type("ruSt").map(|x| x.data()).collect();
+        assert_eq!(data, [&b"This is a secret message!"[..], b"third"]);
+
+        let args = RemoveArgs {
+            nth: Some(2),
+            ..args
+        };
+        assert!(remove(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_leaves_file_untouched() {
+        let path = temp_png("dry_run");
+        let original = std::fs::read(&path).unwrap();
+        let write = || WriteArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message: Some(String::from("hidden")),
+            write: write(),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            write: write(),
+            ..Default::default()
+        };
+        remove(&args).unwrap();
+
+        let mut args = swap_args(&path, "ruSt", "ruSp");
+        args.write = write();
+        swap(&args).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_backup() {
+        let path = temp_png("backup");
+        let original = std::fs::read(&path).unwrap();
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("ruSt"),
+            write: WriteArgs {
+                backup: Some(BackupMode::Simple),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        remove(&args).unwrap();
+
+        let backup = backup_path(&path, BackupMode::Simple);
+        assert_eq!(std::fs::read(&backup).unwrap(), original);
+        assert_ne!(std::fs::read(&path).unwrap(), original);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(backup).unwrap();
+    }
+
+    #[test]
+    fn test_numbered_backup() {
+        let path = temp_png("numbered_backup");
+        let mut args = swap_args(&path, "ruSt", "ruSp");
+        args.write.backup = Some(BackupMode::Numbered);
+        swap(&args).unwrap();
+        let edited = std::fs::read(&path).unwrap();
+        let mut args = swap_args(&path, "ruSp", "ruSq");
+        args.write.backup = Some(BackupMode::Numbered);
+        swap(&args).unwrap();
+
+        let first = PathBuf::from(format!("{}.bak.1", path.display()));
+        let second = PathBuf::from(format!("{}.bak.2", path.display()));
+        assert_eq!(std::fs::read(&first).unwrap(), testing_png().as_bytes());
+        assert_eq!(std::fs::read(&second).unwrap(), edited);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_reports_failure() {
+        let path = temp_png("dry_run_failure");
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: String::from("IEND"),
+            write: WriteArgs {
+                dry_run: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // removing IEND would produce an invalid file
+        assert!(remove(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_message_file() {
+        let path = temp_png("encode_message_file");
+        let message_path = temp_path("encode_message_file_payload");
+        std::fs::write(&message_path, "line one\nline two\n").unwrap();
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            message_file: Some(message_path.clone()),
+            ..Default::default()
+        };
+        encode(&args).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(
+            png.chunk_by_type("teSt").unwrap().data_as_string().unwrap(),
+            "line one\nline two\n"
+        );
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(message_path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_message_sources() {
+        let path = temp_png("encode_message_sources");
+        let mut args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: Some(String::from("teSt")),
+            ..Default::default()
+        };
+        assert!(encode(&args).is_err());
+
+        args.message = Some(String::from("inline"));
+        args.message_file = Some(temp_path("encode_message_sources_payload"));
+        assert!(encode(&args).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_match_offsets() {
+        assert_eq!(match_offsets(b"a TODO and todo", b"TODO", false), vec![2]);
+        assert_eq!(
+            match_offsets(b"a TODO and todo", b"TODO", true),
+            vec![2, 11]
+        );
+        assert_eq!(match_offsets(b"aaa", b"aa", false), vec![0, 1]);
+        assert!(match_offsets(b"short", b"longer pattern", false).is_empty());
+        assert!(match_offsets(b"anything", b"", false).is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let path = temp_png("find");
+        let mut args = FindArgs {
+            file_path: path.clone(),
+            pattern: String::from("SECRET"),
+            ..Default::default()
+        };
+        assert!(find(&args).unwrap().is_empty());
+
+        args.ignore_case = true;
+        assert_eq!(
+            find(&args).unwrap(),
+            [(ChunkType::from_str("ruSt").unwrap(), 10)]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_repair() {
+        let png = testing_png();
+        let mut bytes = png.as_bytes();
+        let secret_end = 8 + 25 + png.chunks()[1].total_bytes();
+        bytes[secret_end - 1] ^= 0xff;
+
+        let path = temp_path("repair");
+        std::fs::write(&path, bytes).unwrap();
+        assert!(Png::from_file(&path).is_err());
+
+        let args = RepairArgs {
+            file_path: path.clone(),
+            ..Default::default()
+        };
+        repair(&args).unwrap();
+        let repaired = Png::from_file(&path).unwrap();
+        assert_eq!(repaired.as_bytes(), png.as_bytes());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_chunks_json() {
+        let png = testing_png();
+        let json: serde_json::Value =
+            serde_json::from_str(&chunks_json(&chunk_infos(png.chunks()))).unwrap();
+        let chunks = json.as_array().unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["offset"], 8);
+        assert_eq!(chunks[1]["type"], "ruSt");
+        assert_eq!(chunks[1]["length"], 25);
+        assert_eq!(chunks[1]["crc"], png.chunks()[1].crc());
+        assert_eq!(chunks[1]["offset"], 8 + 12 + 13);
+        assert_eq!(chunks[1]["critical"], false);
+        assert_eq!(chunks[1]["safe_to_copy"], true);
+    }
+
+    #[test]
+    fn test_chunks_json_empty() {
+        let json: serde_json::Value = serde_json::from_str(&chunks_json(&[])).unwrap();
+        assert_eq!(json, serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        let json: serde_json::Value = serde_json::from_str(&json_string("a\"b\\c\n")).unwrap();
+        assert_eq!(json, "a\"b\\c\n");
+    }
+
+    #[test]
+    fn test_copy() {
+        let source = temp_png("copy_source");
+        let destination = temp_path("copy_destination");
+        let mut png = testing_png();
+        png.remove_first_chunk("ruSt").unwrap();
+        png.write_file(&destination).unwrap();
+
+        let args = CopyArgs {
+            source: source.clone(),
+            destination: destination.clone(),
+            chunk_type: String::from("ruSt"),
+            ..Default::default()
+        };
+        copy(&args).unwrap();
+
+        let png = Png::from_file(&destination).unwrap();
+        assert_eq!(
+            png.chunk_by_type("ruSt").unwrap().data_as_string().unwrap(),
+            "This is a secret message!"
+        );
+        std::fs::remove_file(source).unwrap();
+        std::fs::remove_file(destination).unwrap();
+    }
+
+    #[test]
+    fn test_copy_missing_chunk() {
+        let source = temp_png("copy_missing_source");
+        let destination = temp_png("copy_missing_destination");
+        let args = CopyArgs {
+            source: source.clone(),
+            destination: destination.clone(),
+            chunk_type: String::from("noPe"),
+            ..Default::default()
+        };
+        assert!(copy(&args).is_err());
+        std::fs::remove_file(source).unwrap();
+        std::fs::remove_file(destination).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// Marks chunk data that starts with an envelope header.
+pub const MAGIC: [u8; 4] = *b"PNGm";
+pub const VERSION: u8 = 1;
+/// Magic bytes, version byte and content type byte.
+pub const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// What kind of payload an envelope carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Text = 0,
+    Binary = 1,
+    Compressed = 2,
+    /// A transform bitmask byte followed by data run through those transforms.
+    Transformed = 3,
+}
+
+impl TryFrom<u8> for ContentType {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ContentType::Text),
+            1 => Ok(ContentType::Binary),
+            2 => Ok(ContentType::Compressed),
+            3 => Ok(ContentType::Transformed),
+            _ => Err(format!("Unknown envelope content type {}", value).into()),
+        }
+    }
+}
+
+impl Display for ContentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentType::Text => write!(f, "text"),
+            ContentType::Binary => write!(f, "binary"),
+            ContentType::Compressed => write!(f, "compressed"),
+            ContentType::Transformed => write!(f, "transformed"),
+        }
+    }
+}
+
+/// Prepends an envelope header to `payload`.
+pub fn wrap(content_type: ContentType, payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.push(content_type as u8);
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Splits an envelope into its content type and payload.
+///
+/// Returns `None` when `data` doesn't start with the magic bytes, so it can be treated as raw
+/// data written before envelopes existed.
+pub fn unwrap(data: &[u8]) -> Result<Option<(ContentType, &[u8])>> {
+    if data.len() < HEADER_LEN || data[..MAGIC.len()] != MAGIC {
+        return Ok(None);
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(format!("Unsupported envelope version {}", version).into());
+    }
+    let content_type = ContentType::try_from(data[MAGIC.len() + 1])?;
+    Ok(Some((content_type, &data[HEADER_LEN..])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let data = wrap(ContentType::Text, b"hello");
+        assert_eq!(&data[..4], b"PNGm");
+        assert_eq!(data.len(), HEADER_LEN + 5);
+
+        let (content_type, payload) = unwrap(&data).unwrap().unwrap();
+        assert_eq!(content_type, ContentType::Text);
+        assert_eq!(payload, b"hello");
+    }
+
+    #[test]
+    fn test_unwrap_legacy_data() {
+        assert!(unwrap(b"just a raw message").unwrap().is_none());
+        assert!(unwrap(b"PNG").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unwrap_unknown_version() {
+        let mut data = wrap(ContentType::Binary, &[1, 2, 3]);
+        data[4] = 9;
+        assert!(unwrap(&data).is_err());
+    }
+
+    #[test]
+    fn test_unwrap_unknown_content_type() {
+        let mut data = wrap(ContentType::Binary, &[1, 2, 3]);
+        data[5] = 42;
+        assert!(unwrap(&data).is_err());
+    }
+}
@@ -0,0 +1,20 @@
+//! Hide messages in PNG files.
+//!
+//! [`Png`], [`Chunk`] and [`ChunkType`] read and edit files chunk by chunk; [`commands`] holds
+//! the logic behind each CLI subcommand, driven by the structs in [`args`].
+
+pub mod args;
+pub mod chunk;
+pub mod chunk_type;
+pub mod color;
+pub mod commands;
+pub mod envelope;
+pub mod png;
+pub mod transform;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use png::Png;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,17 +1,158 @@
-mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
+use clap::Parser;
+use pngme::args::{Cli, Command, Verbosity};
+use pngme::commands::{self, Level, Report};
+use pngme::Result;
+use std::io::Write;
+use std::process::ExitCode;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
-
-use std::str::FromStr;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-use chunk_type::ChunkType;
+/// Prints a command's messages, leaving out the ones `verbosity` hides.
+fn print_report(report: &Report, verbosity: Verbosity) {
+    for (level, message) in &report.messages {
+        match level {
+            Level::Output => println!("{}", message),
+            Level::Info if verbosity > Verbosity::Quiet => println!("{}", message),
+            Level::Notice if verbosity > Verbosity::Quiet => eprintln!("{}", message),
+            Level::Detail if verbosity == Verbosity::Verbose => eprintln!("{}", message),
+            Level::Error => eprintln!("{}", message),
+            _ => {}
+        }
+    }
+}
 
-fn main() {
-    let ct = ChunkType::from_str("Ru1t");
-    println!("{}", ct.is_err());
+/// Runs the subcommand, returning the exit code for runs that finish without an error.
+fn run(cli: Cli) -> Result<ExitCode> {
+    let verbosity = cli.verbosity();
+    let result = match cli.command {
+        Command::Encode(args) => commands::encode(&args).map(|x| print_report(&x, verbosity)),
+        Command::EncodeDir(args) => {
+            let outcome = commands::encode_dir(&args)?;
+            print_report(&outcome.report, verbosity);
+            match outcome.value {
+                0 => Ok(()),
+                failed => Err(format!("{} file(s) failed", failed).into()),
+            }
+        }
+        Command::Decode(args) => {
+            let outcome = commands::decode(&args)?;
+            print_report(&outcome.report, verbosity);
+            if let Some(text) = outcome.value {
+                println!("{}", text);
+            }
+            Ok(())
+        }
+        Command::Remove(args) => {
+            let outcome = commands::remove(&args)?;
+            if args.print {
+                for chunk in &outcome.value {
+                    std::io::stdout().write_all(chunk.data())?;
+                }
+            }
+            print_report(&outcome.report, verbosity);
+            Ok(())
+        }
+        Command::Swap(args) => commands::swap(&args).map(|x| print_report(&x, verbosity)),
+        Command::ReplaceData(args) => {
+            commands::replace_data(&args).map(|x| print_report(&x, verbosity))
+        }
+        Command::SetFlags(args) => commands::set_flags(&args).map(|x| print_report(&x, verbosity)),
+        Command::Copy(args) => commands::copy(&args).map(|x| print_report(&x, verbosity)),
+        Command::Merge(args) => commands::merge(&args).map(|x| print_report(&x.report, verbosity)),
+        Command::Diff(args) => {
+            for line in commands::diff(&args)? {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Command::Print(args) => {
+            let listing = commands::print(&args)?;
+            for line in listing.lines {
+                println!("{}", line);
+            }
+            for warning in listing.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            Ok(())
+        }
+        Command::Stats(args) => {
+            let stats = commands::stats(&args)?;
+            if args.json {
+                println!("{}", stats.to_json());
+            } else {
+                println!("{}", stats.report(args.bytes));
+            }
+            Ok(())
+        }
+        Command::Plan(args) => {
+            let plan = commands::plan(args.message_len, args.max_chunk_size)?;
+            println!(
+                "{} chunk(s), {} bytes of framing overhead",
+                plan.chunks, plan.overhead
+            );
+            Ok(())
+        }
+        Command::Info(args) => {
+            for line in commands::info(&args.chunk_type)? {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Command::Verify(args) => {
+            let report = commands::verify(&args)?;
+            println!("{}", report);
+            report.check()
+        }
+        Command::Normalize(args) => {
+            commands::normalize(&args.file_path, &args.output_file, &args.write)
+                .map(|x| print_report(&x, verbosity))
+        }
+        Command::Canonicalize(args) => {
+            commands::canonicalize(&args).map(|x| print_report(&x, verbosity))
+        }
+        Command::Minify(args) => {
+            commands::minify(&args).map(|x| print_report(&x.report, verbosity))
+        }
+        Command::Export(args) => {
+            let files = commands::export(&args)?;
+            println!(
+                "Exported {} chunk(s) to {}",
+                files.len(),
+                args.out_dir.display()
+            );
+            Ok(())
+        }
+        Command::Assemble(args) => commands::assemble(&args).map(|x| print_report(&x, verbosity)),
+        Command::Find(args) => {
+            let found = commands::find(&args)?;
+            for (chunk_type, offset) in &found {
+                println!("{} at offset {}", chunk_type, offset);
+            }
+            if found.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+            Ok(())
+        }
+        Command::Repair(args) => commands::repair(&args).map(|x| print_report(&x, verbosity)),
+        Command::Roundtrip(args) => match commands::roundtrip_check(&args.file_path)? {
+            Some(offset) => {
+                println!("Round trip diverges at offset {}", offset);
+                Err("Round trip check failed".into())
+            }
+            None => {
+                println!("Round trip is byte-identical");
+                Ok(())
+            }
+        },
+    };
+    result.map(|()| ExitCode::SUCCESS)
 }
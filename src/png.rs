@@ -1,49 +1,551 @@
-use crate::chunk::Chunk;
+use crate::chunk::{self, Chunk, ChunkError, ChunkRef};
 use crate::ChunkType;
 use crate::Result;
 use std::{
+    collections::HashMap,
     fmt::{Display, Formatter},
+    fs,
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
     str::FromStr,
 };
-struct Png {
+pub struct Png {
     header: &'static [u8; 8],
     chunks: Vec<Chunk>,
 }
 
 #[derive(Debug)]
-pub struct PNGCreationError;
-impl std::fmt::Display for PNGCreationError {
+pub enum PngError {
+    Io(std::io::Error),
+    /// The data doesn't start with the PNG signature
+    BadSignature,
+    /// A chunk declares more data than the caller's limit allows
+    ChunkTooLarge {
+        offset: usize,
+        length: u32,
+        max: u32,
+    },
+    /// A chunk type's third letter is lowercase, which the spec reserves
+    ReservedBit {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    Chunk(ChunkError),
+    /// A chunk failed to parse, with where in the file it starts
+    ChunkAt {
+        index: usize,
+        offset: usize,
+        error: ChunkError,
+    },
+}
+impl std::fmt::Display for PngError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Invalid PNG")
+        match self {
+            PngError::Io(e) => write!(f, "Could not read PNG: {}", e),
+            PngError::BadSignature => write!(f, "Not a PNG file (bad signature)"),
+            PngError::ChunkTooLarge {
+                offset,
+                length,
+                max,
+            } => write!(
+                f,
+                "Chunk at offset {} declares {} bytes, more than the limit of {}",
+                offset, length, max
+            ),
+            PngError::ReservedBit { index, chunk_type } => write!(
+                f,
+                "Chunk {} has type {}, whose lowercase third letter is reserved",
+                index, chunk_type
+            ),
+            PngError::Chunk(e) => write!(f, "Invalid PNG: {}", e),
+            PngError::ChunkAt {
+                index,
+                offset,
+                error,
+            } => write!(
+                f,
+                "Invalid PNG: chunk {} at offset {}: {}",
+                index, offset, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngError::Io(e) => Some(e),
+            PngError::BadSignature
+            | PngError::ChunkTooLarge { .. }
+            | PngError::ReservedBit { .. } => None,
+            PngError::Chunk(e) | PngError::ChunkAt { error: e, .. } => Some(e),
+        }
+    }
+}
+
+/// A problem [`Png::from_bytes_lenient`] skipped past.
+#[derive(Debug)]
+pub struct ParseWarning {
+    /// Where in the buffer the problem was found
+    pub offset: usize,
+    pub error: PngError,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.error)
+    }
+}
+
+impl From<std::io::Error> for PngError {
+    fn from(e: std::io::Error) -> Self {
+        PngError::Io(e)
+    }
+}
+
+impl From<ChunkError> for PngError {
+    fn from(e: ChunkError) -> Self {
+        PngError::Chunk(e)
     }
 }
 
-impl std::error::Error for PNGCreationError {}
+/// Ancillary chunks that the spec requires to appear before `PLTE`.
+const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+
+/// Ancillary chunks that the spec requires to appear after `PLTE` and before the first `IDAT`.
+const BEFORE_IDAT: [&str; 5] = ["bKGD", "hIST", "pHYs", "sPLT", "tRNS"];
+
+/// Position of a chunk's section in a canonically ordered file.
+fn canonical_rank(chunk: &Chunk) -> u8 {
+    let chunk_type = chunk.chunk_type().to_string();
+    match chunk_type.as_str() {
+        "IHDR" => 0,
+        t if BEFORE_PLTE.contains(&t) => 1,
+        "PLTE" => 2,
+        "IDAT" => 4,
+        "IEND" => 5,
+        _ => 3,
+    }
+}
 
 impl Png {
     pub const STANDARD_HEADER: &'static [u8; 8] = &[137, 80, 78, 71, 13, 10, 26, 10];
+    /// The largest chunk length the PNG specification allows (2^31 - 1).
+    pub const DEFAULT_MAX_CHUNK_LEN: u32 = Chunk::MAX_LENGTH;
 
     pub fn header(&self) -> &[u8; 8] {
-        &self.header
+        self.header
     }
 
-    pub fn chunks(&self) -> &Vec<Chunk> {
+    pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
+    /// Iterates over the chunks in file order, the same as `for chunk in &png`.
+    pub fn iter(&self) -> std::slice::Iter<'_, Chunk> {
+        self.chunks.iter()
+    }
+
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             header: Png::STANDARD_HEADER,
-            chunks: chunks,
+            chunks,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::result::Result<Png, PngError> {
+        Png::from_reader(BufReader::new(fs::File::open(path)?))
+    }
+
+    /// Checks the signature and parses every chunk from a stream, reading one chunk at a time.
+    ///
+    /// A chunk that fails to parse is reported as [`PngError::ChunkAt`], with its index and
+    /// byte offset in the stream.
+    pub fn from_reader<R: Read>(mut reader: R) -> std::result::Result<Png, PngError> {
+        let mut signature = [0; 8];
+        if chunk::read_full(&mut reader, &mut signature)? < 8 || &signature != Png::STANDARD_HEADER
+        {
+            return Err(PngError::BadSignature);
         }
+        let mut offset = signature.len();
+        let mut chunks = Vec::new();
+        loop {
+            let at = |error| PngError::ChunkAt {
+                index: chunks.len(),
+                offset,
+                error,
+            };
+            let mut bytes = vec![0; 8];
+            match chunk::read_full(&mut reader, &mut bytes)? {
+                0 => break,
+                8 => {}
+                _ => return Err(at(ChunkError::TooShort)),
+            }
+            let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+            if length > Png::DEFAULT_MAX_CHUNK_LEN {
+                return Err(PngError::ChunkTooLarge {
+                    offset,
+                    length,
+                    max: Png::DEFAULT_MAX_CHUNK_LEN,
+                });
+            }
+            (&mut reader)
+                .take(length as u64 + 4)
+                .read_to_end(&mut bytes)?;
+            let chunk = Chunk::try_from(bytes.as_slice()).map_err(at)?;
+            offset += chunk.total_bytes();
+            chunks.push(chunk);
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Checks the signature and parses every chunk of an in-memory PNG.
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Png, PngError> {
+        Png::parse(bytes, Png::DEFAULT_MAX_CHUNK_LEN, false, |bytes| {
+            Chunk::try_from(bytes)
+        })
+    }
+
+    /// Checks the signature and parses every chunk of `bytes` without copying any chunk data.
+    ///
+    /// Call [`ChunkRef::to_chunk`] on the chunks worth keeping, or use [`Png::from_bytes`] to
+    /// copy them all.
+    pub fn chunk_refs(bytes: &[u8]) -> std::result::Result<Vec<ChunkRef<'_>>, PngError> {
+        if bytes.len() < 8 || &bytes[..8] != Png::STANDARD_HEADER {
+            return Err(PngError::BadSignature);
+        }
+        let mut chunks = Vec::new();
+        let mut i = 8;
+        while i < bytes.len() {
+            let chunk = ChunkRef::parse(&bytes[i..]).map_err(|error| PngError::ChunkAt {
+                index: chunks.len(),
+                offset: i,
+                error,
+            })?;
+            i += chunk.total_bytes();
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+
+    /// Like [`Png::from_file`], but also rejects chunk types whose reserved bit is invalid.
+    pub fn from_file_strict<P: AsRef<Path>>(path: P) -> std::result::Result<Png, PngError> {
+        let png = Png::from_file(path)?;
+        if let Some((index, chunk)) = png
+            .chunks
+            .iter()
+            .enumerate()
+            .find(|(_, x)| !x.chunk_type().is_reserved_bit_valid())
+        {
+            return Err(PngError::ReservedBit {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+            });
+        }
+        Ok(png)
+    }
+
+    /// Parses as much of a possibly corrupt PNG as it can.
+    ///
+    /// When a chunk fails to parse, a warning is recorded and parsing resumes at the next offset
+    /// holding a whole chunk with a valid crc. The returned `Png` holds every chunk that parsed.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> (Png, Vec<ParseWarning>) {
+        let mut warnings = Vec::new();
+        if bytes.len() < 8 || &bytes[..8] != Png::STANDARD_HEADER {
+            warnings.push(ParseWarning {
+                offset: 0,
+                error: PngError::BadSignature,
+            });
+        }
+        let mut chunks = Vec::new();
+        let mut i = 8;
+        while i < bytes.len() {
+            match Chunk::try_from(&bytes[i..]) {
+                Ok(chunk) => {
+                    i += chunk.total_bytes();
+                    chunks.push(chunk);
+                }
+                Err(error) => {
+                    warnings.push(ParseWarning {
+                        offset: i,
+                        error: error.into(),
+                    });
+                    match Png::resync(bytes, i + 1) {
+                        Some(next) => i = next,
+                        None => break,
+                    }
+                }
+            }
+        }
+        (Png::from_chunks(chunks), warnings)
     }
 
+    /// Finds the first offset from `start` where a valid chunk begins.
+    fn resync(bytes: &[u8], start: usize) -> Option<usize> {
+        (start..bytes.len().saturating_sub(11)).find(|&i| {
+            bytes[i + 4..i + 8].iter().all(|x| x.is_ascii_alphabetic())
+                && Chunk::try_from(&bytes[i..]).is_ok()
+        })
+    }
+
+    /// Reads the file, rejecting any chunk that declares more than `max_chunk_len` bytes of data
+    /// before its data is copied.
+    pub fn from_file_with_limits<P: AsRef<Path>>(
+        path: P,
+        max_chunk_len: u32,
+    ) -> std::result::Result<Png, PngError> {
+        let bytes = fs::read(path)?;
+        Png::parse(&bytes, max_chunk_len, false, |bytes| Chunk::try_from(bytes))
+    }
+
+    /// Finds the first chunk of `chunk_type` without loading the rest of the file.
+    ///
+    /// Other chunks are skipped as they stream past, so memory stays bounded by the one chunk
+    /// returned.
+    pub fn scan_file<P: AsRef<Path>>(path: P, chunk_type: &str) -> Result<Option<Chunk>> {
+        Png::scan_reader(BufReader::new(fs::File::open(path)?), chunk_type)
+    }
+
+    fn scan_reader<R: Read>(mut reader: R, chunk_type: &str) -> Result<Option<Chunk>> {
+        let mut signature = [0; 8];
+        if chunk::read_full(&mut reader, &mut signature)? < 8 || &signature != Png::STANDARD_HEADER
+        {
+            return Err(PngError::BadSignature.into());
+        }
+        loop {
+            let mut header = [0; 8];
+            match chunk::read_full(&mut reader, &mut header)? {
+                0 => return Ok(None),
+                8 => {}
+                _ => return Err(ChunkError::TooShort.into()),
+            }
+            if header[4..8] == *chunk_type.as_bytes() {
+                return Chunk::from_reader(&mut header.chain(&mut reader));
+            }
+            // skip the data and crc without keeping them
+            let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64 + 4;
+            if io::copy(&mut (&mut reader).take(length), &mut io::sink())? < length {
+                return Err(ChunkError::TooShort.into());
+            }
+        }
+    }
+
+    /// Parses a PNG from a stream, calling `progress` with the bytes consumed so far after each
+    /// chunk.
+    ///
+    /// `total_len` is the size of the stream, as a progress bar needs anyway; a chunk declaring
+    /// more data than that is rejected before anything is allocated for it.
+    pub fn from_reader_with_progress<R: Read, F: FnMut(u64)>(
+        mut reader: R,
+        total_len: u64,
+        mut progress: F,
+    ) -> Result<Png> {
+        let mut signature = [0; 8];
+        if chunk::read_full(&mut reader, &mut signature)? < 8 || &signature != Png::STANDARD_HEADER
+        {
+            return Err(PngError::BadSignature.into());
+        }
+        let mut consumed = signature.len() as u64;
+        let mut chunks = Vec::new();
+        loop {
+            let mut header = [0; 8];
+            match chunk::read_full(&mut reader, &mut header)? {
+                0 => break,
+                8 => {}
+                _ => return Err(ChunkError::TooShort.into()),
+            }
+            let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+            if length as u64 > total_len {
+                return Err(PngError::ChunkTooLarge {
+                    offset: consumed as usize,
+                    length,
+                    max: total_len.min(u32::MAX as u64) as u32,
+                }
+                .into());
+            }
+            let chunk =
+                Chunk::from_reader(&mut header.chain(&mut reader))?.ok_or(ChunkError::TooShort)?;
+            consumed += chunk.total_bytes() as u64;
+            chunks.push(chunk);
+            progress(consumed);
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Reads the file without checking chunk crcs, so corrupt chunks can be inspected.
+    pub fn from_file_unchecked<P: AsRef<Path>>(path: P) -> std::result::Result<Png, PngError> {
+        let bytes = fs::read(path)?;
+        Png::parse(
+            &bytes,
+            Png::DEFAULT_MAX_CHUNK_LEN,
+            false,
+            Chunk::try_from_unchecked,
+        )
+    }
+
+    /// Parses without checking chunk crcs and ignores anything after `IEND`, for tools that
+    /// rebuild every chunk anyway.
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> std::result::Result<Png, PngError> {
+        Png::parse(
+            bytes,
+            Png::DEFAULT_MAX_CHUNK_LEN,
+            true,
+            Chunk::try_from_unchecked,
+        )
+    }
+
+    /// Parses every chunk after the signature, stopping early after `IEND` if `stop_at_iend`.
+    fn parse(
+        value: &[u8],
+        max_chunk_len: u32,
+        stop_at_iend: bool,
+        parse_chunk: impl Fn(&[u8]) -> std::result::Result<Chunk, ChunkError>,
+    ) -> std::result::Result<Png, PngError> {
+        // check the signature before touching any chunk data
+        if value.len() < 8 || &value[0..8] != Png::STANDARD_HEADER {
+            return Err(PngError::BadSignature);
+        }
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut i: usize = 8;
+        while i < value.len() {
+            if let Some(length) = value.get(i..i + 4) {
+                let length = u32::from_be_bytes(length.try_into().unwrap());
+                if length > max_chunk_len {
+                    return Err(PngError::ChunkTooLarge {
+                        offset: i,
+                        length,
+                        max: max_chunk_len,
+                    });
+                }
+            }
+            let chunk = parse_chunk(&value[i..]).map_err(|error| PngError::ChunkAt {
+                index: chunks.len(),
+                offset: i,
+                error,
+            })?;
+            i += chunk.total_bytes();
+            let is_end = chunk.chunk_type().as_bytes() == b"IEND";
+            chunks.push(chunk);
+            if stop_at_iend && is_end {
+                break;
+            }
+        }
+        Ok(Png {
+            header: Png::STANDARD_HEADER,
+            chunks,
+        })
+    }
+
+    /// Writes the file after checking it with [`Png::validate`].
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.validate()?;
+        self.write_file_unchecked(path)
+    }
+
+    /// Writes the file as-is, even if it is structurally invalid.
+    ///
+    /// The bytes go to a sibling temporary file that is synced, given the original's
+    /// permissions and then renamed over `path`, so a crash mid-write never leaves the original
+    /// half-written. Being a sibling, the temporary file is on the same filesystem and the
+    /// rename can't fail for crossing one. It is removed again if any step fails.
+    pub fn write_file_unchecked<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp_path = Png::temp_path_for(path);
+        let written = self
+            .write_temp(&tmp_path, path)
+            .and_then(|_| fs::rename(&tmp_path, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn write_temp(&self, tmp_path: &Path, path: &Path) -> io::Result<()> {
+        let mut file = fs::File::create(tmp_path)?;
+        // one buffer sized up front and one write, however many chunks there are
+        file.write_all(&self.as_bytes())?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()
+    }
+
+    fn temp_path_for(path: &Path) -> PathBuf {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+    }
+
+    /// Checks that the file starts with `IHDR` and ends with a single `IEND`.
+    pub fn validate(&self) -> Result<()> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().to_string() == "IHDR" => {}
+            _ => return Err("First chunk is not IHDR".into()),
+        }
+        match self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type().to_string() == "IEND")
+        {
+            Some(pos) if pos == self.chunks.len() - 1 => Ok(()),
+            Some(_) => Err("Chunks found after IEND".into()),
+            None => Err("Missing IEND chunk".into()),
+        }
+    }
+
+    /// Adds `chunk` at the end of the file, keeping a trailing `IEND` chunk last.
+    ///
+    /// Other chunks keep their order and their bytes as read, stored crc included, so writing
+    /// the file back differs from the original only by the new chunk.
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        match self.chunks.last() {
+            Some(last) if last.chunk_type().to_string() == "IEND" => {
+                let pos = self.chunks.len() - 1;
+                self.chunks.insert(pos, chunk);
+            }
+            _ => self.chunks.push(chunk),
+        }
+    }
+
+    /// Inserts `chunk` where the spec allows it, returning the index it landed at.
+    ///
+    /// Types with a required section, like `gAMA` before `PLTE` or `tRNS` before `IDAT`, go
+    /// before the first chunk of a later section. Any other chunk goes before `IEND`.
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> usize {
+        let latest_rank = |chunk: &Chunk| match canonical_rank(chunk) {
+            3 if !BEFORE_IDAT.contains(&chunk.chunk_type().to_string().as_str()) => 4,
+            rank => rank,
+        };
+        let rank = latest_rank(&chunk);
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| canonical_rank(x) > rank)
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk);
+        index
+    }
+
+    /// Inserts `chunk` before the chunk currently at `index`; `index` may equal the chunk count.
+    pub fn insert_chunk_at(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(format!(
+                "Chunk index {} out of range for {} chunks",
+                index,
+                self.chunks.len()
+            )
+            .into());
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
     }
 
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        let ct = ChunkType::from_str(chunk_type).unwrap();
+        let ct = ChunkType::from_str(chunk_type)?;
         if let Some(pos) = self.chunks.iter().position(|x| x.chunk_type() == &ct) {
             Ok(self.chunks.remove(pos))
         } else {
@@ -51,75 +553,346 @@ impl Png {
         }
     }
 
-    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let ct = ChunkType::from_str(chunk_type).unwrap();
+    /// Removes the `n`th (zero-based) chunk of `chunk_type`.
+    pub fn remove_nth_chunk(&mut self, chunk_type: &str, n: usize) -> Result<Chunk> {
+        let ct = ChunkType::from_str(chunk_type)?;
+        match self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.chunk_type() == &ct)
+            .nth(n)
+        {
+            Some((pos, _)) => Ok(self.chunks.remove(pos)),
+            None => Err(format!("Fewer than {} chunks of type {}", n + 1, chunk_type).into()),
+        }
+    }
+
+    /// Removes every chunk of `chunk_type`, returning them in file order. Removing none is not
+    /// an error.
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|x| x.chunk_type().as_bytes() == chunk_type.as_bytes());
+        self.chunks = kept;
+        removed
+    }
+
+    /// Drops every ancillary chunk except those whose type is listed in `keep`, returning how many
+    /// were removed.
+    pub fn retain_critical(&mut self, keep: &[&str]) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(|x| {
+            x.chunk_type().is_critical() || keep.contains(&x.chunk_type().to_string().as_str())
+        });
+        before - self.chunks.len()
+    }
+
+    /// Replaces the first chunk of `chunk_type` in place, returning the old chunk.
+    pub fn replace_first_chunk(&mut self, chunk_type: &str, chunk: Chunk) -> Result<Chunk> {
+        let ct = ChunkType::from_str(chunk_type)?;
         if let Some(pos) = self.chunks.iter().position(|x| x.chunk_type() == &ct) {
-            Some(&self.chunks[pos])
+            Ok(std::mem::replace(&mut self.chunks[pos], chunk))
         } else {
-            None
+            Err(crate::Error::from("Chunk not found"))
         }
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = Vec::new();
-        bytes.extend_from_slice(self.header);
+    /// Replaces the `n`th (zero-based) chunk of `chunk_type`.
+    pub fn replace_nth_chunk(
+        &mut self,
+        chunk_type: &str,
+        n: usize,
+        new_chunk: Chunk,
+    ) -> Result<()> {
+        let ct = ChunkType::from_str(chunk_type)?;
+        match self
+            .chunks
+            .iter_mut()
+            .filter(|x| x.chunk_type() == &ct)
+            .nth(n)
+        {
+            Some(chunk) => {
+                *chunk = new_chunk;
+                Ok(())
+            }
+            None => Err(format!("Fewer than {} chunks of type {}", n + 1, chunk_type).into()),
+        }
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        let ct = ChunkType::from_str(chunk_type).ok()?;
+        self.chunks.iter().find(|x| x.chunk_type() == &ct)
+    }
+
+    pub fn chunk_by_type_mut(&mut self, chunk_type: &str) -> Option<&mut Chunk> {
+        let ct = ChunkType::from_str(chunk_type).ok()?;
+        self.chunks.iter_mut().find(|x| x.chunk_type() == &ct)
+    }
+
+    /// Every chunk of `chunk_type` in file order; empty if no type matches it exactly.
+    pub fn chunks_by_type<'a>(
+        &'a self,
+        chunk_type: &'a str,
+    ) -> impl Iterator<Item = &'a Chunk> + 'a {
+        self.chunks
+            .iter()
+            .filter(move |x| x.chunk_type().as_bytes() == chunk_type.as_bytes())
+    }
+
+    /// How many chunks of `chunk_type` the file holds.
+    pub fn count_by_type(&self, chunk_type: &str) -> usize {
+        self.chunks_by_type(chunk_type).count()
+    }
+
+    /// Joins the data of every chunk of `chunk_type` in file order.
+    pub fn chunk_data_concat(&self, chunk_type: &str) -> Vec<u8> {
+        self.chunks_by_type(chunk_type)
+            .flat_map(|x| x.data())
+            .copied()
+            .collect()
+    }
+
+    /// Returns the type and text of every chunk whose data is valid UTF-8, in file order.
+    pub fn all_strings(&self) -> Vec<(String, String)> {
         self.chunks
             .iter()
-            .map(|x| bytes.extend_from_slice(&x.as_bytes()));
+            .filter_map(|x| {
+                let text = x.data_as_string().ok()?;
+                Some((x.chunk_type().to_string(), text))
+            })
+            .collect()
+    }
+
+    /// Reads the image width and height from the start of the `IHDR` chunk.
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        let ihdr = self.chunk_by_type("IHDR").ok_or("Missing IHDR chunk")?;
+        let data = ihdr.data();
+        if data.len() < 8 {
+            return Err("IHDR chunk is too short".into());
+        }
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        Ok((width, height))
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// How many chunks of each type the file holds.
+    pub fn type_counts(&self) -> HashMap<ChunkType, usize> {
+        let mut counts = HashMap::new();
+        for chunk in &self.chunks {
+            *counts.entry(chunk.chunk_type().clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Moves ancillary chunks into fixed sections and sorts each section by type and data.
+    ///
+    /// Types the spec requires before `PLTE` go just before `PLTE`, or before the first `IDAT` if
+    /// there is no palette. Every other ancillary chunk goes just before the first `IDAT`, so
+    /// where metadata sat in the input doesn't change the output. Critical chunks keep their
+    /// order, as do the APNG frame chunks `fcTL` and `fdAT`, whose position carries meaning.
+    pub fn canonical_order(&mut self) {
+        let (fixed, moved): (Vec<Chunk>, Vec<Chunk>) =
+            std::mem::take(&mut self.chunks).into_iter().partition(|x| {
+                x.chunk_type().is_critical()
+                    || matches!(x.chunk_type().to_string().as_str(), "fcTL" | "fdAT")
+            });
+        let (mut early, mut late): (Vec<Chunk>, Vec<Chunk>) = moved
+            .into_iter()
+            .partition(|x| BEFORE_PLTE.contains(&x.chunk_type().to_string().as_str()));
+        for section in [&mut early, &mut late] {
+            section.sort_by(|a, b| {
+                (a.chunk_type().as_bytes(), a.data()).cmp(&(b.chunk_type().as_bytes(), b.data()))
+            });
+        }
+
+        let position = |types: &[&str]| {
+            fixed
+                .iter()
+                .position(|x| types.contains(&x.chunk_type().to_string().as_str()))
+        };
+        let idat_at = position(&["IDAT"])
+            .or_else(|| position(&["IEND"]))
+            .unwrap_or(fixed.len());
+        let plte_at = position(&["PLTE"]).map_or(idat_at, |x| x.min(idat_at));
+
+        let mut fixed = fixed.into_iter();
+        self.chunks.extend(fixed.by_ref().take(plte_at));
+        self.chunks.extend(early);
+        self.chunks.extend(fixed.by_ref().take(idat_at - plte_at));
+        self.chunks.extend(late);
+        self.chunks.extend(fixed);
+    }
+
+    /// Every chunk grouped by type in one pass, each group in file order.
+    pub fn group_by_type(&self) -> HashMap<ChunkType, Vec<&Chunk>> {
+        let mut groups: HashMap<ChunkType, Vec<&Chunk>> = HashMap::new();
+        for chunk in &self.chunks {
+            groups
+                .entry(chunk.chunk_type().clone())
+                .or_default()
+                .push(chunk);
+        }
+        groups
+    }
+
+    /// An animated PNG announces itself with an `acTL` chunk.
+    pub fn is_apng(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    /// Reads the number of frames from the start of the `acTL` chunk.
+    pub fn frame_count(&self) -> Option<u32> {
+        let data = self.chunk_by_type("acTL")?.data();
+        let bytes = data.get(0..4)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reports structural problems that don't stop the file from loading.
+    ///
+    /// Checks for critical chunks that appear more than once (`IDAT` may repeat), chunks whose
+    /// length field disagrees with their data, chunks after `IEND`, and a missing `IEND`.
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = Vec::new();
+
+        let mut checked: Vec<&ChunkType> = Vec::new();
+        for chunk in self.chunks.iter() {
+            let ct = chunk.chunk_type();
+            if ct.is_ancillary() || ct.to_string() == "IDAT" || checked.contains(&ct) {
+                continue;
+            }
+            checked.push(ct);
+            let count = self.chunks.iter().filter(|x| x.chunk_type() == ct).count();
+            if count > 1 {
+                warnings.push(format!("Critical chunk {} appears {} times", ct, count));
+            }
+        }
+
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if !chunk.is_length_consistent() {
+                warnings.push(format!(
+                    "Chunk {} ({}) declares {} bytes but holds {}",
+                    i,
+                    chunk.chunk_type(),
+                    chunk.declared_length(),
+                    chunk.data().len()
+                ));
+            }
+        }
+
+        match self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type().to_string() == "IEND")
+        {
+            Some(pos) if pos + 1 < self.chunks.len() => warnings.push(format!(
+                "{} chunk(s) found after IEND",
+                self.chunks.len() - pos - 1
+            )),
+            Some(_) => {}
+            None => warnings.push(String::from("Missing IEND chunk")),
+        }
+
+        warnings
+    }
+
+    /// Size of the serialized file: the signature plus every chunk with its framing.
+    pub fn total_size(&self) -> usize {
+        self.header.len() + self.chunks.iter().map(|x| x.total_bytes()).sum::<usize>()
+    }
+
+    /// Sum of the chunk data lengths, without the signature or chunk framing.
+    pub fn data_size(&self) -> usize {
+        self.chunks.iter().map(|x| x.data().len()).sum()
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.total_size());
+        bytes.extend_from_slice(self.header);
+        for chunk in &self.chunks {
+            chunk.write_into(&mut bytes);
+        }
         bytes
     }
 }
 
+/// Assembles a PNG from scratch, adding `IEND` on [`PngBuilder::build`].
+#[derive(Debug, Default)]
+pub struct PngBuilder {
+    ihdr: Option<Chunk>,
+    chunks: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    pub fn new() -> PngBuilder {
+        PngBuilder::default()
+    }
+
+    /// Sets an 8-bit RGBA `IHDR` with the given size, replacing any earlier one.
+    pub fn ihdr(mut self, width: u32, height: u32) -> PngBuilder {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        // bit depth, color type, compression, filter, interlace
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+        self.ihdr = Some(Chunk::new(ChunkType::from_str("IHDR").unwrap(), data));
+        self
+    }
+
+    pub fn idat(self, data: Vec<u8>) -> PngBuilder {
+        self.chunk(ChunkType::from_str("IDAT").unwrap(), data)
+    }
+
+    /// Adds a chunk after everything added so far.
+    pub fn chunk(mut self, chunk_type: ChunkType, data: Vec<u8>) -> PngBuilder {
+        self.chunks.push(Chunk::new(chunk_type, data));
+        self
+    }
+
+    pub fn build(self) -> Result<Png> {
+        let ihdr = self.ihdr.ok_or("PngBuilder needs an IHDR before build")?;
+        let mut chunks = Vec::with_capacity(self.chunks.len() + 2);
+        chunks.push(ihdr);
+        chunks.extend(self.chunks);
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
 impl TryFrom<&[u8]> for Png {
-    type Error = PNGCreationError;
+    type Error = PngError;
 
     fn try_from(value: &[u8]) -> std::prelude::v1::Result<Self, Self::Error> {
-        if value.len() >= 8 {
-            let header = &value[0..8];
-            if header == Png::STANDARD_HEADER {
-                let mut chunks: Vec<Chunk> = Vec::new();
-                let mut i: usize = 8;
-                let mut continue_reading = true;
-                while continue_reading {
-                    let len = u32::from_be_bytes(value[i..i + 4].try_into().unwrap());
-                    let chunk = Chunk::try_from(&value[i..i + len as usize]).unwrap();
-                    chunks.push(chunk);
-                    i += len as usize;
-                    if i >= value.len() {
-                        continue_reading = false;
-                    }
-                }
-                Ok(Png {
-                    header: Png::STANDARD_HEADER,
-                    chunks: chunks,
-                })
-            } else {
-                Err(PNGCreationError)
-            }
-        } else {
-            Err(PNGCreationError)
-        }
+        Png::from_bytes(value)
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = std::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
     }
 }
 
 impl Display for Png {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let data: Vec<u8> = self
-            .chunks()
-            .iter()
-            .flat_map(|x| x.data())
-            .copied()
-            .collect();
-        let str = std::str::from_utf8(data.as_slice()).unwrap_or("Invalid UTF-8");
-
-        write!(f, "{}", str)
+        for chunk in &self.chunks {
+            writeln!(f, "{}", chunk)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk::Chunk;
+    use crate::chunk::{Chunk, ChunkError};
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
 
@@ -189,6 +962,277 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_not_a_png() {
+        let inputs: [&[u8]; 4] = [
+            &[],
+            &[137, 80, 78],
+            &[255, 216, 255, 224, 0, 16, 74, 70, 73, 70, 0, 1],
+            b"just some plain text, definitely not an image",
+        ];
+        for input in inputs {
+            let png = Png::try_from(input);
+            assert!(matches!(png, Err(PngError::BadSignature)));
+        }
+    }
+
+    #[test]
+    fn test_from_file_not_a_png() {
+        let path = std::env::temp_dir().join(format!("pngme_{}_not_png.txt", std::process::id()));
+        std::fs::write(&path, "hello").unwrap();
+        let err = Png::from_file(&path).err().unwrap();
+        assert!(matches!(err, PngError::BadSignature));
+        assert_eq!(err.to_string(), "Not a PNG file (bad signature)");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_file_leaves_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("pngme_{}_atomic", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.png");
+        fs::write(&path, "old contents").unwrap();
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.write_file(&path).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), PNG_FILE);
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_file_removes_temp_file_on_error() {
+        let dir = std::env::temp_dir().join(format!("pngme_{}_atomic_err", std::process::id()));
+        // renaming a file over a non-empty directory fails
+        let path = dir.join("out.png");
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("keep"), "").unwrap();
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.write_file(&path).is_err());
+
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(path.join("keep").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("pngme_{}_perms.png", std::process::id()));
+        fs::write(&path, "old contents").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.write_file(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(fs::read(&path).unwrap(), PNG_FILE);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_from_file_with_limits() {
+        let path = std::env::temp_dir().join(format!("pngme_{}_limits.png", std::process::id()));
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        // a chunk claiming 4 GB of data with nothing behind it
+        bytes.extend_from_slice(&[0xff, 0xff, 0xff, 0xf0, 82, 117, 83, 116]);
+        fs::write(&path, &bytes).unwrap();
+
+        let err = Png::from_file_with_limits(&path, 1024).err().unwrap();
+        assert!(matches!(
+            err,
+            PngError::ChunkTooLarge {
+                offset: 8,
+                length: 0xfffffff0,
+                max: 1024
+            }
+        ));
+        assert!(err.to_string().contains("4294967280"));
+        assert!(matches!(
+            Png::from_file(&path),
+            Err(PngError::ChunkTooLarge { .. })
+        ));
+
+        fs::write(&path, PNG_FILE).unwrap();
+        assert!(Png::from_file_with_limits(&path, 1024).is_err());
+        assert!(Png::from_file_with_limits(&path, 8192).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_scan_reader() {
+        let mut chunks = vec![Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            vec![0; 13],
+        )];
+        for _ in 0..50 {
+            chunks.push(Chunk::new(
+                ChunkType::from_str("IDAT").unwrap(),
+                vec![7; 64 * 1024],
+            ));
+        }
+        chunks.push(chunk_from_strings("ruSt", "needle").unwrap());
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        let bytes = Png::from_chunks(chunks).as_bytes();
+
+        let found = Png::scan_reader(io::Cursor::new(&bytes), "ruSt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.data(), b"needle");
+        assert!(Png::scan_reader(io::Cursor::new(&bytes), "noNe")
+            .unwrap()
+            .is_none());
+        assert!(Png::scan_reader(io::Cursor::new(&bytes[..1000]), "ruSt").is_err());
+        assert!(Png::scan_reader(io::Cursor::new(b"not a png"), "ruSt").is_err());
+    }
+
+    #[test]
+    fn test_from_file_strict() {
+        let path = std::env::temp_dir().join(format!("pngme_{}_strict.png", std::process::id()));
+        fs::write(&path, PNG_FILE).unwrap();
+        assert!(Png::from_file_strict(&path).is_ok());
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruet", "reserved").unwrap());
+        fs::write(&path, png.as_bytes()).unwrap();
+        assert!(Png::from_file(&path).is_ok());
+        let err = Png::from_file_strict(&path).err().unwrap();
+        assert!(
+            matches!(&err, PngError::ReservedBit { chunk_type, .. } if chunk_type.to_string() == "ruet")
+        );
+        assert!(err.to_string().contains("ruet"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let path = std::env::temp_dir().join(format!("pngme_{}_scan.png", std::process::id()));
+        fs::write(&path, PNG_FILE).unwrap();
+        let chunk = Png::scan_file(&path, "RuSt").unwrap().unwrap();
+        assert_eq!(chunk.data(), b"hey");
+        assert!(chunk.verify_crc());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_io_error() {
+        use std::error::Error;
+
+        let path = std::env::temp_dir().join("pngme_missing_file.png");
+        let err = Png::from_file(&path).err().unwrap();
+        assert!(matches!(err, PngError::Io(_)));
+        assert!(err.source().unwrap().is::<std::io::Error>());
+    }
+
+    #[test]
+    fn test_chunk_error() {
+        use std::error::Error;
+
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 5, 82, 117]);
+        let err = Png::try_from(bytes.as_ref()).err().unwrap();
+        assert!(matches!(
+            err,
+            PngError::ChunkAt {
+                index: 0,
+                offset: 8,
+                ..
+            }
+        ));
+        assert!(err.source().unwrap().is::<ChunkError>());
+    }
+
+    #[test]
+    fn test_bad_chunk_type_in_file() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 49, 50, 51, 52, 0, 0, 0, 0]);
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(
+            png,
+            Err(PngError::ChunkAt {
+                error: ChunkError::BadChunkType([49, 50, 51, 52]),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_chunk_refs() {
+        let refs = Png::chunk_refs(&PNG_FILE).unwrap();
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let owned: Vec<Chunk> = refs.iter().map(|x| x.to_chunk()).collect();
+        assert_eq!(owned, png.chunks());
+
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8 + 8] ^= 0xff;
+        assert!(matches!(
+            Png::chunk_refs(&bytes),
+            Err(PngError::ChunkAt {
+                index: 0,
+                offset: 8,
+                error: ChunkError::InvalidCrc,
+            })
+        ));
+        assert!(matches!(
+            Png::chunk_refs(b"not a png"),
+            Err(PngError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let png = Png::from_reader(std::io::Cursor::new(&PNG_FILE[..])).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE);
+
+        let err = Png::from_reader(&b"GIF89a.."[..]).err().unwrap();
+        assert!(matches!(err, PngError::BadSignature));
+    }
+
+    #[test]
+    fn test_from_reader_reports_failing_chunk() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offset = 8 + png.chunks()[0].total_bytes();
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[offset + 8] ^= 0xff;
+
+        let err = Png::from_reader(bytes.as_slice()).err().unwrap();
+        assert!(matches!(
+            err,
+            PngError::ChunkAt {
+                index: 1,
+                error: ChunkError::InvalidCrc,
+                ..
+            }
+        ));
+        assert!(err
+            .to_string()
+            .contains(&format!("chunk 1 at offset {}", offset)));
+
+        let err = Png::from_reader(&PNG_FILE[..offset + 3]).err().unwrap();
+        assert!(matches!(
+            err,
+            PngError::ChunkAt {
+                index: 1,
+                error: ChunkError::TooShort,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_bad_signature_has_no_source() {
+        use std::error::Error;
+
+        assert!(PngError::BadSignature.source().is_none());
+    }
+
     #[test]
     fn test_invalid_chunk() {
         let mut chunk_bytes: Vec<u8> = testing_chunks()
@@ -218,6 +1262,26 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_iterate_chunks() {
+        let png = testing_png();
+        let mut types: Vec<String> = Vec::new();
+        for chunk in &png {
+            types.push(chunk.chunk_type().to_string());
+        }
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+
+        let critical = png
+            .into_iter()
+            .filter(|x| x.chunk_type().is_critical())
+            .count();
+        assert_eq!(critical, 2);
+
+        let lengths: Vec<u32> = png.iter().map(|x| x.length()).collect();
+        assert_eq!(lengths.len(), png.chunks().len());
+        assert!(png.iter().eq(png.chunks().iter()));
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -226,6 +1290,22 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Another middle").unwrap());
+
+        let data: Vec<String> = png
+            .chunks_by_type("miDl")
+            .map(|x| x.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, ["I am another chunk", "Another middle"]);
+        assert_eq!(png.count_by_type("miDl"), 2);
+        assert_eq!(png.count_by_type("FrSt"), 1);
+        assert_eq!(png.count_by_type("NoNe"), 0);
+        assert_eq!(png.count_by_type("12"), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -235,6 +1315,92 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunks = png.chunks();
+        assert_eq!(chunks[chunks.len() - 2].chunk_type().to_string(), "TeSt");
+        assert_eq!(chunks[chunks.len() - 1].chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk_at(1, chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["FrSt", "TeSt", "miDl", "LASt"]);
+
+        png.insert_chunk_at(4, chunk_from_strings("EnDs", "Last").unwrap())
+            .unwrap();
+        assert_eq!(png.chunks()[4].chunk_type().to_string(), "EnDs");
+    }
+
+    #[test]
+    fn test_insert_chunk_by_ordering_rules() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let types =
+            |png: &Png| -> Vec<String> { png.iter().map(|x| x.chunk_type().to_string()).collect() };
+        assert_eq!(
+            types(&png),
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]
+        );
+
+        let chunk = |chunk_type: &str| chunk_from_strings(chunk_type, "x").unwrap();
+        assert_eq!(png.insert_chunk(chunk("tEXt")), 6);
+        assert_eq!(png.insert_chunk(chunk("cHRM")), 3);
+        assert_eq!(png.insert_chunk(chunk("PLTE")), 4);
+        assert_eq!(png.insert_chunk(chunk("tRNS")), 6);
+        assert_eq!(
+            types(&png),
+            [
+                "IHDR", "sRGB", "gAMA", "cHRM", "PLTE", "pHYs", "tRNS", "IDAT", "RuSt", "tEXt",
+                "IEND"
+            ]
+        );
+        assert!(png.validate().is_ok());
+
+        let mut empty = Png::from_chunks(Vec::new());
+        assert_eq!(empty.insert_chunk(chunk("ruSt")), 0);
+    }
+
+    #[test]
+    fn test_insert_chunk_out_of_range() {
+        let mut png = testing_png();
+        let result = png.insert_chunk_at(4, chunk_from_strings("TeSt", "Message").unwrap());
+        assert!(result.is_err());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_replace_nth_chunk() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("TeSt", "one").unwrap(),
+            chunk_from_strings("TeSt", "two").unwrap(),
+            chunk_from_strings("TeSt", "three").unwrap(),
+        ]);
+        png.replace_nth_chunk("TeSt", 1, chunk_from_strings("TeSt", "new").unwrap())
+            .unwrap();
+        let data: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, vec!["one", "new", "three"]);
+    }
+
+    #[test]
+    fn test_replace_nth_chunk_missing() {
+        let mut png = testing_png();
+        let result = png.replace_nth_chunk("FrSt", 1, chunk_from_strings("FrSt", "new").unwrap());
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -244,6 +1410,21 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_nth_chunk() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("TeSt", "one").unwrap(),
+            chunk_from_strings("FrSt", "other").unwrap(),
+            chunk_from_strings("TeSt", "two").unwrap(),
+        ]);
+        let removed = png.remove_nth_chunk("TeSt", 1).unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "two");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_nth_chunk("TeSt", 1).is_err());
+        assert!(png.remove_nth_chunk("12", 0).is_err());
+        assert_eq!(png.chunks().len(), 2);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -258,6 +1439,388 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_total_and_data_size() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.total_size(), PNG_FILE.len());
+        assert_eq!(png.total_size(), png.as_bytes().len());
+        assert_eq!(
+            png.data_size(),
+            png.total_size() - 8 - 12 * png.chunks().len()
+        );
+
+        let png = testing_png();
+        assert_eq!(png.total_size(), png.as_bytes().len());
+        assert_eq!(Png::from_chunks(Vec::new()).total_size(), 8);
+    }
+
+    #[test]
+    fn test_from_bytes_round_trip() {
+        let png = Png::from_bytes(&PNG_FILE).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        let again = Png::from_bytes(&png.as_bytes()).unwrap();
+        assert_eq!(again.chunks(), png.chunks());
+
+        let built = testing_png();
+        assert_eq!(
+            Png::from_bytes(&built.as_bytes()).unwrap().chunks(),
+            built.chunks()
+        );
+        assert!(matches!(
+            Png::from_bytes(b"nope"),
+            Err(PngError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn test_as_bytes_matches_per_chunk_serialization() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut naive = Png::STANDARD_HEADER.to_vec();
+        for chunk in png.chunks() {
+            naive.extend(chunk.as_bytes());
+        }
+        let bytes = png.as_bytes();
+        assert_eq!(bytes, naive);
+        assert_eq!(bytes.capacity(), png.total_size());
+    }
+
+    #[test]
+    fn test_from_reader_with_progress() {
+        let mut seen = Vec::new();
+        let png =
+            Png::from_reader_with_progress(&PNG_FILE[..], PNG_FILE.len() as u64, |x| seen.push(x))
+                .unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        assert_eq!(seen.len(), png.chunks().len());
+        assert!(seen.windows(2).all(|x| x[0] < x[1]));
+        assert_eq!(seen.last(), Some(&(PNG_FILE.len() as u64)));
+    }
+
+    #[test]
+    fn test_from_reader_with_progress_rejects_oversized_chunk() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(u32::MAX.to_be_bytes());
+        bytes.extend(b"IDAT");
+        let err = Png::from_reader_with_progress(&bytes[..], bytes.len() as u64, |_| {})
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("more than the limit of 16"));
+    }
+
+    #[test]
+    fn test_type_counts() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "again").unwrap());
+        png.append_chunk(chunk_from_strings("teXt", "other").unwrap());
+        assert_eq!(png.chunk_count(), 5);
+
+        let counts = png.type_counts();
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[&ChunkType::from_str("miDl").unwrap()], 2);
+        assert_eq!(counts[&ChunkType::from_str("FrSt").unwrap()], 1);
+        assert_eq!(counts[&ChunkType::from_str("teXt").unwrap()], 1);
+        assert_eq!(counts.get(&ChunkType::from_str("IDAT").unwrap()), None);
+        assert_eq!(counts.values().sum::<usize>(), png.chunk_count());
+    }
+
+    #[test]
+    fn test_remove_all_chunks() {
+        let mut png = testing_png();
+        for (i, message) in ["one", "two", "three"].iter().enumerate() {
+            png.insert_chunk_at(i * 2, chunk_from_strings("spLt", message).unwrap())
+                .unwrap();
+        }
+        assert_eq!(png.chunks().len(), 6);
+
+        let removed = png.remove_all_chunks("spLt");
+        let data: Vec<&[u8]> = removed.iter().map(|x| x.data()).collect();
+        assert_eq!(data, [&b"one"[..], b"two", b"three"]);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+
+        assert!(png.remove_all_chunks("spLt").is_empty());
+        assert!(png.remove_all_chunks("sp1t").is_empty());
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("aaAa", "moves up").unwrap());
+        png.canonical_order();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        // RuSt is critical, so unlike aaAa it keeps its place after IDAT
+        assert_eq!(
+            types,
+            ["IHDR", "gAMA", "sRGB", "aaAa", "pHYs", "IDAT", "RuSt", "IEND"]
+        );
+
+        let text = || chunk_from_strings("tEXt", "Comment\0hi").unwrap();
+        let mut before = Png::try_from(&PNG_FILE[..]).unwrap();
+        before.insert_chunk_at(1, text()).unwrap();
+        let mut after = Png::try_from(&PNG_FILE[..]).unwrap();
+        after.append_chunk(text());
+        before.canonical_order();
+        after.canonical_order();
+        assert_eq!(before.as_bytes(), after.as_bytes());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient() {
+        let mut bytes = PNG_FILE.to_vec();
+        // corrupt the data of the RuSt chunk, which sits between healthy ones
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offset = 8 + png.chunks()[..5]
+            .iter()
+            .map(|x| x.total_bytes())
+            .sum::<usize>();
+        bytes[offset + 8] ^= 0xff;
+
+        let (recovered, warnings) = Png::from_bytes_lenient(&bytes);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].offset, offset);
+        assert!(matches!(
+            warnings[0].error,
+            PngError::Chunk(ChunkError::InvalidCrc)
+        ));
+        let types: Vec<String> = recovered
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "IEND"]);
+
+        let (png, warnings) = Png::from_bytes_lenient(&PNG_FILE);
+        assert!(warnings.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_group_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "second first").unwrap());
+        png.append_chunk(chunk_from_strings("miDl", "second middle").unwrap());
+        png.append_chunk(chunk_from_strings("FrSt", "third first").unwrap());
+
+        let groups = png.group_by_type();
+        assert_eq!(groups.len(), 3);
+        let data = |chunk_type: &str| -> Vec<String> {
+            groups[&ChunkType::from_str(chunk_type).unwrap()]
+                .iter()
+                .map(|x| x.data_as_string().unwrap())
+                .collect()
+        };
+        assert_eq!(
+            data("FrSt"),
+            ["I am the first chunk", "second first", "third first"]
+        );
+        assert_eq!(data("miDl"), ["I am another chunk", "second middle"]);
+        assert_eq!(data("LASt"), ["I am the last chunk"]);
+    }
+
+    #[test]
+    fn test_retain_critical() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.retain_critical(&["gAMA"]), 2);
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "RuSt", "IEND"]);
+
+        assert_eq!(png.retain_critical(&[]), 1);
+        assert!(png.chunks().iter().all(|x| x.chunk_type().is_critical()));
+        assert!(png.validate().is_ok());
+    }
+
+    #[test]
+    fn test_chunk_data_concat() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("TeSt", "one ").unwrap(),
+            chunk_from_strings("miDl", "skipped").unwrap(),
+            chunk_from_strings("TeSt", "two ").unwrap(),
+            chunk_from_strings("TeSt", "three").unwrap(),
+        ]);
+        assert_eq!(png.chunk_data_concat("TeSt"), b"one two three");
+        assert!(png.chunk_data_concat("NoNe").is_empty());
+    }
+
+    #[test]
+    fn test_all_strings() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("TeSt", "hello").unwrap(),
+            Chunk::new(ChunkType::from_str("biNa").unwrap(), vec![0xff, 0xfe, 0x00]),
+            chunk_from_strings("miDl", "world").unwrap(),
+        ]);
+        assert_eq!(
+            png.all_strings(),
+            vec![
+                ("TeSt".to_string(), "hello".to_string()),
+                ("miDl".to_string(), "world".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let chunk_type = ChunkType::from_str("IHDR").unwrap();
+        let data = vec![0, 0, 7, 128, 0, 0, 4, 56, 8, 6, 0, 0, 0];
+        let png = Png::from_chunks(vec![Chunk::new(chunk_type, data)]);
+        assert_eq!(png.dimensions().unwrap(), (1920, 1080));
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.dimensions().unwrap(), (50, 50));
+    }
+
+    #[test]
+    fn test_dimensions_missing_ihdr() {
+        assert!(testing_png().dimensions().is_err());
+    }
+
+    #[test]
+    fn test_dimensions_short_ihdr() {
+        let png = Png::from_chunks(vec![chunk_from_strings("IHDR", "short").unwrap()]);
+        assert!(png.dimensions().is_err());
+    }
+
+    #[test]
+    fn test_apng() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(!png.is_apng());
+        assert_eq!(png.frame_count(), None);
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        // 12 frames, looping forever
+        let actl = Chunk::new(
+            ChunkType::from_str("acTL").unwrap(),
+            vec![0, 0, 0, 12, 0, 0, 0, 0],
+        );
+        png.insert_chunk_at(1, actl).unwrap();
+        assert!(png.is_apng());
+        assert_eq!(png.frame_count(), Some(12));
+    }
+
+    #[test]
+    fn test_frame_count_short_actl() {
+        let png = Png::from_chunks(vec![chunk_from_strings("acTL", "ab").unwrap()]);
+        assert!(png.is_apng());
+        assert_eq!(png.frame_count(), None);
+    }
+
+    #[test]
+    fn test_builder() {
+        let png = PngBuilder::new()
+            .ihdr(3, 2)
+            .chunk(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec())
+            .idat(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        assert!(png.validate().is_ok());
+        assert_eq!(png.dimensions().unwrap(), (3, 2));
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "IDAT", "IEND"]);
+
+        let reparsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(reparsed.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_builder_requires_ihdr() {
+        assert!(PngBuilder::new().idat(vec![0]).build().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_missing_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_first_chunk("IEND").unwrap();
+        assert!(png.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_missing_ihdr() {
+        let png = testing_png();
+        assert!(png.validate().is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_unchecked() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offset = 8 + png.chunks()[0].total_bytes();
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[offset + 8] ^= 0xff;
+        bytes.extend_from_slice(b"trailing garbage");
+
+        let salvaged = Png::from_bytes_unchecked(&bytes).unwrap();
+        assert_eq!(salvaged.chunks().len(), png.chunks().len());
+        assert!(!salvaged.chunks()[1].verify_crc());
+        assert!(Png::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_lint_truncated_chunk() {
+        let mut bytes = PNG_FILE[..PNG_FILE.len() - 12].to_vec();
+        // cut the last data byte off RuSt, which now runs to the end of the file
+        bytes.remove(bytes.len() - 5);
+        let png = Png::parse(
+            &bytes,
+            Png::DEFAULT_MAX_CHUNK_LEN,
+            false,
+            Chunk::try_from_unchecked,
+        )
+        .unwrap();
+        assert!(png
+            .lint()
+            .contains(&String::from("Chunk 5 (RuSt) declares 3 bytes but holds 2")));
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_lint_clean_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_duplicate_critical() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert_eq!(png.lint(), vec!["Critical chunk IHDR appears 2 times"]);
+    }
+
+    #[test]
+    fn test_lint_chunk_after_iend() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "late").unwrap(),
+        ]);
+        assert_eq!(png.lint(), vec!["1 chunk(s) found after IEND"]);
+    }
+
+    #[test]
+    fn test_lint_missing_iend() {
+        let png = testing_png();
+        assert_eq!(png.lint(), vec!["Missing IEND chunk"]);
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,270 @@
+use crate::Result;
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+/// Header bits recording which transforms a message went through.
+pub const COMPRESS: u8 = 1;
+pub const ENCRYPT: u8 = 1 << 1;
+pub const BASE64_TEXT: u8 = 1 << 2;
+
+/// A reversible step applied to a message before it's stored in a chunk.
+pub trait MessageTransform {
+    /// The header bit that records this transform.
+    fn bit(&self) -> u8;
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+    fn decode(&self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// zlib compression.
+pub struct Compress;
+
+impl MessageTransform for Compress {
+    fn bit(&self) -> u8 {
+        COMPRESS
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        ZlibDecoder::new(&bytes[..])
+            .read_to_end(&mut out)
+            .map_err(|e| format!("Message is not valid zlib data: {}", e))?;
+        Ok(out)
+    }
+}
+
+/// XChaCha20-Poly1305 keyed from a passphrase through Argon2id.
+///
+/// The stored form is a random salt and nonce followed by the ciphertext and its Poly1305 tag,
+/// so a wrong passphrase and a tampered message are both rejected on decode.
+pub struct Encrypt {
+    passphrase: String,
+}
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+impl Encrypt {
+    pub fn new(passphrase: &str) -> Encrypt {
+        Encrypt {
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| format!("Could not derive a key from the passphrase: {}", e))?;
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+impl MessageTransform for Encrypt {
+    fn bit(&self) -> u8 {
+        ENCRYPT
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let mut out = vec![0; SALT_LEN + NONCE_LEN];
+        getrandom::getrandom(&mut out)
+            .map_err(|e| format!("Could not get random bytes for encryption: {}", e))?;
+        let (salt, nonce) = out.split_at(SALT_LEN);
+        let ciphertext = self
+            .cipher(salt)?
+            .encrypt(XNonce::from_slice(nonce), bytes.as_slice())
+            .map_err(|_| "Could not encrypt the message")?;
+        out.extend(ciphertext);
+        Ok(out)
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if bytes.len() < SALT_LEN + NONCE_LEN + TAG_LEN {
+            return Err("Encrypted message is too short".into());
+        }
+        let (salt, rest) = bytes.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        Ok(self
+            .cipher(salt)?
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Wrong passphrase, or the message is corrupted")?)
+    }
+}
+
+/// Standard base64, so binary results stay printable.
+pub struct Base64;
+
+impl MessageTransform for Base64 {
+    fn bit(&self) -> u8 {
+        BASE64_TEXT
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(BASE64.encode(bytes).into_bytes())
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(BASE64
+            .decode(bytes)
+            .map_err(|e| format!("Message is not valid base64: {}", e))?)
+    }
+}
+
+/// Transforms applied in a fixed order on encode (compress, encrypt, base64) and in reverse on
+/// decode. The encoded form starts with a bitmask byte naming the transforms applied.
+pub struct Pipeline {
+    transforms: Vec<Box<dyn MessageTransform>>,
+}
+
+impl Pipeline {
+    pub fn new(compress: bool, passphrase: Option<&str>, base64: bool) -> Pipeline {
+        let mut transforms: Vec<Box<dyn MessageTransform>> = Vec::new();
+        if compress {
+            transforms.push(Box::new(Compress));
+        }
+        if let Some(passphrase) = passphrase {
+            transforms.push(Box::new(Encrypt::new(passphrase)));
+        }
+        if base64 {
+            transforms.push(Box::new(Base64));
+        }
+        Pipeline { transforms }
+    }
+
+    /// Rebuilds the pipeline described by a header byte. Needs a passphrase if it has the
+    /// encrypt bit.
+    pub fn from_mask(mask: u8, passphrase: Option<&str>) -> Result<Pipeline> {
+        if mask & !(COMPRESS | ENCRYPT | BASE64_TEXT) != 0 {
+            return Err(format!("Unknown transform bits {:#04x}", mask).into());
+        }
+        let passphrase = match (mask & ENCRYPT != 0, passphrase) {
+            (true, None) => return Err("Message is encrypted; give --passphrase".into()),
+            (true, Some(passphrase)) => Some(passphrase),
+            (false, _) => None,
+        };
+        Ok(Pipeline::new(
+            mask & COMPRESS != 0,
+            passphrase,
+            mask & BASE64_TEXT != 0,
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.transforms.iter().fold(0, |mask, x| mask | x.bit())
+    }
+
+    /// Runs `bytes` through every transform and prepends the header byte.
+    pub fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let mut bytes = bytes;
+        for transform in &self.transforms {
+            bytes = transform.encode(bytes)?;
+        }
+        bytes.insert(0, self.mask());
+        Ok(bytes)
+    }
+
+    /// Reads the header byte of `data` and undoes the transforms it names.
+    pub fn decode(data: &[u8], passphrase: Option<&str>) -> Result<Vec<u8>> {
+        let (&mask, payload) = data.split_first().ok_or("Transformed message is empty")?;
+        let pipeline = Pipeline::from_mask(mask, passphrase)?;
+        let mut bytes = payload.to_vec();
+        for transform in pipeline.transforms.iter().rev() {
+            bytes = transform.decode(bytes)?;
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"This is a secret message! This is a secret message!";
+
+    #[test]
+    fn test_compress() {
+        let encoded = Compress.encode(MESSAGE.to_vec()).unwrap();
+        assert!(encoded.len() < MESSAGE.len());
+        assert_eq!(Compress.decode(encoded).unwrap(), MESSAGE);
+        assert!(Compress.decode(b"not zlib".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let encrypt = Encrypt::new("hunter2");
+        let encoded = encrypt.encode(MESSAGE.to_vec()).unwrap();
+        assert_eq!(
+            encoded.len(),
+            SALT_LEN + NONCE_LEN + MESSAGE.len() + TAG_LEN
+        );
+        assert!(!encoded.windows(6).any(|x| x == b"secret"));
+        assert_eq!(encrypt.decode(encoded.clone()).unwrap(), MESSAGE);
+        // a fresh salt and nonce every time
+        assert_ne!(encrypt.encode(MESSAGE.to_vec()).unwrap(), encoded);
+
+        let err = Encrypt::new("hunter3").decode(encoded).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Wrong passphrase, or the message is corrupted"
+        );
+    }
+
+    #[test]
+    fn test_encrypt_rejects_tampering() {
+        let encrypt = Encrypt::new("hunter2");
+        let encoded = encrypt.encode(MESSAGE.to_vec()).unwrap();
+        for i in [0, SALT_LEN, SALT_LEN + NONCE_LEN, encoded.len() - 1] {
+            let mut tampered = encoded.clone();
+            tampered[i] ^= 1;
+            assert!(encrypt.decode(tampered).is_err(), "byte {}", i);
+        }
+        assert!(encrypt
+            .decode(encoded[..encoded.len() - 1].to_vec())
+            .is_err());
+        assert!(encrypt.decode(vec![0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        let encoded = Base64.encode(b"hey".to_vec()).unwrap();
+        assert_eq!(encoded, b"aGV5");
+        assert_eq!(Base64.decode(encoded).unwrap(), b"hey");
+    }
+
+    #[test]
+    fn test_pipeline_round_trip() {
+        let pipeline = Pipeline::new(true, Some("hunter2"), true);
+        assert_eq!(pipeline.mask(), COMPRESS | ENCRYPT | BASE64_TEXT);
+        let encoded = pipeline.encode(MESSAGE.to_vec()).unwrap();
+        assert_eq!(encoded[0], pipeline.mask());
+        // base64 runs last, so everything after the header is printable
+        assert!(encoded[1..].iter().all(|x| x.is_ascii_graphic()));
+
+        assert_eq!(
+            Pipeline::decode(&encoded, Some("hunter2")).unwrap(),
+            MESSAGE
+        );
+        let err = Pipeline::decode(&encoded, None).unwrap_err();
+        assert_eq!(err.to_string(), "Message is encrypted; give --passphrase");
+    }
+
+    #[test]
+    fn test_pipeline_unknown_bits() {
+        assert!(Pipeline::decode(&[0x80, b'a'], None).is_err());
+        assert_eq!(Pipeline::decode(&[0, b'a'], None).unwrap(), b"a");
+    }
+}